@@ -0,0 +1,65 @@
+//! Runs a program on a bare CPU6502 in lockstep with a reference log and shows where they part.
+//!
+//! `cargo run --example trace_diff -- <program> <load address> <reference log> [context lines]`
+//!
+//! The registers start as on the first line of the log. An iNES image is loaded like NROM,
+//! `cargo run --example trace_diff -- nestest.nes 0 nestest.log` runs nestest in automation mode.
+
+use rusty6502::cpu6502::memory::{Memory, Memory6502};
+use rusty6502::cpu6502::trace_diff::{TraceDiff, TraceRecord};
+use rusty6502::cpu6502::{CPU6502, CpuRegisters, StatusRegister};
+
+/// Bad arguments or input, exits like the usage message does.
+fn fail(message: &str) -> !{
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+fn main() -> std::io::Result<()>{
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4{
+        fail(&format!("usage: {} <program> <load address> <reference log> [context lines]", args[0]));
+    }
+    let program = std::fs::read(&args[1])?;
+    let load = u16::from_str_radix(args[2].trim_start_matches('$'), 16)
+        .unwrap_or_else(|_| fail(&format!("load address '{}' is not hex", args[2])));
+    let context = args.get(4).map_or(5, |lines| lines.parse().unwrap_or_else(|_| fail(&format!("context lines '{}' is not a number", lines))));
+
+    let mut memory = Memory::new(64*1024);
+    if program.starts_with(b"NES\x1a"){
+        let prg = program.get(4).and_then(|&banks| program.get(16..16 + banks as usize * 16*1024))
+            .unwrap_or_else(|| fail(&format!("{}: iNES image shorter than its PRG ROM", args[1])));
+        for (i, b) in prg.iter().cycle().take(32*1024).enumerate(){
+            memory.write_memory(0x8000 + i as u16, *b);
+        }
+    }
+    else{
+        for (i, b) in program.iter().enumerate(){
+            memory.write_memory(load.overflowing_add(i as u16).0, *b);
+        }
+    }
+
+    let text = std::fs::read_to_string(&args[3])?;
+    let first = text.lines().find_map(TraceRecord::parse)
+        .unwrap_or_else(|| fail(&format!("{}: no trace lines in the reference log", args[3])));
+    let mut cpu = CPU6502::new();
+    cpu.set_registers(CpuRegisters { A: first.a.unwrap_or(0), X: first.x.unwrap_or(0), Y: first.y.unwrap_or(0),
+        SP: first.sp.unwrap_or(0xff), PC: first.pc, P: StatusRegister::new(first.p.unwrap_or(0x24)) });
+
+    let mut diff = TraceDiff::new(text.as_bytes(), context);
+    while !diff.is_finished(){
+        if let Err(e) = cpu.run_single_traced(&mut memory, &mut diff){
+            eprintln!("CPU stopped: {}", e);
+            break;
+        }
+    }
+    let matched = diff.matched();
+    match diff.finish()? {
+        Some(divergence) => {
+            print!("{}", divergence);
+            std::process::exit(1);
+        }
+        None => println!("{} instructions match the reference", matched),
+    }
+    Ok(())
+}
@@ -1,7 +1,12 @@
-use super::cpu6502::memory::{Memory6502,Memory6502Debug};
+use super::cpu6502::interrupts::InterruptController;
+use super::cpu6502::memory::{Bank,Memory6502,Memory6502Debug,View};
 use std::fs::File;
 use std::io::prelude::*;
-use std::time::{Instant};
+
+/// PAL timing, the raster position, the CIA timers and the time of day are derived from the cycle counter.
+pub(crate) const CYCLES_PER_LINE: u64 = 63;
+pub(crate) const LINES_PER_FRAME: u64 = 312;
+const CYCLES_PER_SECOND: u64 = 985_248;
 
 pub struct C64CharaterRam{
     pub ram: [u8; 1000],
@@ -13,6 +18,12 @@ impl C64CharaterRam {
     }
 }
 
+impl Default for C64CharaterRam {
+    fn default() -> Self{
+        C64CharaterRam::new()
+    }
+}
+
 pub struct C64KeyboadMap{
     pub col: [u8; 8],
 }
@@ -23,12 +34,17 @@ impl C64KeyboadMap {
     }
 }
 
+impl Default for C64KeyboadMap {
+    fn default() -> Self{
+        C64KeyboadMap::new()
+    }
+}
+
 struct C64Timer{
     minute: Option<u8>,
     second: Option<u8>,
     tens_second: Option<u8>,
-    start: Instant,
-    timer_a_last: u128,
+    timer_a_last: u64, //cycle of the last tick
 
     timer_a_latch: u16,
     timer_b_latch: u16,
@@ -42,14 +58,11 @@ struct C64Timer{
 
 impl C64Timer{
     fn new() -> Self{
-        let now = Instant::now();
-        let last = now.elapsed().as_nanos();
         C64Timer {
             minute: None,
             second: None,
             tens_second: None,
-            start: now,
-            timer_a_last: last,
+            timer_a_last: 0,
             timer_a_latch: 0xffff,
             timer_b_latch: 0xffff,
             timer_a_counter: 0xffff,
@@ -61,25 +74,26 @@ impl C64Timer{
         }
     }
 
-    fn tick(&mut self) -> bool{
-        let mut ret = false;     let t = self.start.elapsed().as_nanos();
-        if self.timer_a_ctrl & 0x01 != 0{ //timer A enabled
-            let ticks = (t - self.timer_a_last)/1000; //1MHz need more precise?
-            if ticks > 0 {
-                let (new_val, underflow) = self.timer_a_counter.overflowing_sub(ticks as u16);
-                self.timer_a_counter = new_val;
-                if underflow {
-                    if self.timer_a_ctrl & 0x08 != 0 { // One-shot mode
-                        self.timer_a_ctrl &= 0xfe;
-                    }
-                    self.timer_a_counter = self.timer_a_latch;
-                    self.int_vec_read |= 0x81;
-                    if (self.int_vec_set & 0x01) != 0 { ret = true; }
+    /// Counts down the cycles since the last tick.
+    fn tick(&mut self, cycles: u64){
+        let ticks = cycles.saturating_sub(self.timer_a_last);
+        self.timer_a_last = cycles;
+        if self.timer_a_ctrl & 0x01 != 0 && ticks > 0{ //timer A enabled
+            let (new_val, underflow) = self.timer_a_counter.overflowing_sub(ticks as u16);
+            self.timer_a_counter = new_val;
+            if underflow {
+                if self.timer_a_ctrl & 0x08 != 0 { // One-shot mode
+                    self.timer_a_ctrl &= 0xfe;
                 }
-                self.timer_a_last = t;
+                self.timer_a_counter = self.timer_a_latch;
+                self.int_vec_read |= 0x81;
             }
         }
-        ret
+    }
+
+    /// The CIA holds its interrupt output low while an enabled source is flagged, reading ICR releases it.
+    fn irq(&self) -> bool{
+        self.int_vec_read & self.int_vec_set & 0x1f != 0
     }
 
     fn set_hour(&mut self, _hour: u8){
@@ -143,14 +157,13 @@ impl C64Timer{
         self.timer_b_ctrl = ctrl;
     }
 
-    fn get_hour(&mut self) -> u8{
-        let t = self.start.elapsed();
-        let s = t.as_secs();
-        let ms = t.as_millis();
+    fn get_hour(&mut self, cycles: u64) -> u8{
+        let tenths = cycles * 10 / CYCLES_PER_SECOND;
+        let s = tenths / 10;
 
         self.second = Some((s % 60) as u8);
         self.minute = Some((s/60 % 60) as u8);
-        self.tens_second = Some((ms / 100 % 10) as u8);
+        self.tens_second = Some((tenths % 10) as u8);
 
         (s/3600 % 24) as u8
     }
@@ -184,6 +197,18 @@ impl C64Timer{
         }
     }
 
+    /// Time of day register as a read would return it, without latching or releasing the latch.
+    fn peek_time(&self, register: u16, cycles: u64) -> u8{
+        let tenths = cycles * 10 / CYCLES_PER_SECOND;
+        let s = tenths / 10;
+        match register {
+            0x08 => self.tens_second.unwrap_or((tenths % 10) as u8),
+            0x09 => self.second.unwrap_or((s % 60) as u8),
+            0x0a => self.minute.unwrap_or((s/60 % 60) as u8),
+            _ => (s/3600 % 24) as u8,
+        }
+    }
+
     fn get_timer_int(&mut self) -> u8{
         let r = self.int_vec_read;
         self.int_vec_read = 0;
@@ -212,14 +237,21 @@ pub struct C64Memory{
 
     cia1_timer: C64Timer,
     cia2_timer: C64Timer,
+    cia2_port_a: u8,
+    interrupts: InterruptController,
 
     border_color: u8,
     background_color: u8,
     screen_control1: u8,
     screen_control2: u8,
+    /// CPU cycle of the next bus access.
+    cycles: u64,
 }
 
 impl C64Memory{
+    const CIA1_SOURCE: u8 = 0;
+    const CIA2_SOURCE: u8 = 1;
+
     fn load_rom(path: &str) -> std::io::Result<Vec<u8>>{
         let mut file = File::open(path)?;
         let file_size = file.metadata()?.len();
@@ -228,6 +260,8 @@ impl C64Memory{
         Ok(data)
     }
 
+    /// Loads the ROMs from the roms directory.
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self{
         let kernal = C64Memory::load_rom("roms/kernal.901227-02.bin").expect("no kernal");
         let character_rom = C64Memory::load_rom("roms/characters.901225-01.bin").expect("no char rom");
@@ -235,6 +269,14 @@ impl C64Memory{
         //let external_rom = Some(C64Memory::load_rom("roms/c64_burn-in_7.2_5.6.89.bin").expect("no rom"));
         //let external_rom = Some(C64Memory::load_rom("roms/c64_final_burnin_3.0_5.6.89.bin").expect("no rom"));
         //let external_rom = Some(C64Memory::load_rom("roms/c64_diag_rev4.1.1.bin").expect("no rom"));
+        C64Memory::with_roms(kernal, basic, character_rom)
+    }
+
+    /// Builds the machine around ROM images already in memory: 8K kernal, 8K basic and 4K characters.
+    pub fn with_roms(kernal: Vec<u8>, basic: Vec<u8>, character_rom: Vec<u8>) -> Self{
+        assert_eq!(kernal.len(), 8*1024, "kernal must be 8K");
+        assert_eq!(basic.len(), 8*1024, "basic must be 8K");
+        assert_eq!(character_rom.len(), 4*1024, "character rom must be 4K");
         let external_rom = None;
 
         C64Memory { ram: [0; 64*1024],
@@ -251,21 +293,50 @@ impl C64Memory{
             cia1_port_b_dir: 0,
             cia1_timer: C64Timer::new(),
             cia2_timer: C64Timer::new(),
+            cia2_port_a: 0x03,
+            interrupts: InterruptController::new(),
             border_color: 0,
             background_color:0,
             screen_control1: 0x1b,
-            screen_control2: 0xc8
+            screen_control2: 0xc8,
+            cycles: 0,
         }
     }
 
+    /// Power cycle, RAM and IO start over while the ROMs stay.
+    pub fn reset(&mut self){
+        let kernal = std::mem::take(&mut self.kernal);
+        let basic = std::mem::take(&mut self.basic_rom);
+        let character_rom = std::mem::take(&mut self.character_rom);
+        let external_rom = self.external_rom.take();
+        *self = C64Memory::with_roms(kernal, basic, character_rom);
+        self.external_rom = external_rom;
+    }
+
     pub fn set_keyboard_map(&mut self, keymap: C64KeyboadMap){
         self.keyboard_map = keymap;
     }
 
-    pub fn tick(&mut self) -> bool{
-        let r1 = self.cia1_timer.tick();
-        let r2 = self.cia2_timer.tick();
-        r1 | r2
+    /// Lines the raster position, the timers and the time of day up with the CPU, every bus access after it takes a cycle.
+    pub fn set_cycles(&mut self, cycles: u64){
+        self.cycles = cycles;
+    }
+
+    /// Raster line the beam is on during the next bus access, what `$D012` and bit 7 of `$D011` read.
+    pub fn raster_line(&self) -> u16{
+        (self.cycles / CYCLES_PER_LINE % LINES_PER_FRAME) as u16
+    }
+
+    pub fn tick(&mut self){
+        self.cia1_timer.tick(self.cycles);
+        self.cia2_timer.tick(self.cycles);
+        self.update_interrupt_lines();
+    }
+
+    /// CIA1 drives IRQ and CIA2 drives NMI.
+    fn update_interrupt_lines(&mut self){
+        self.interrupts.set_irq(C64Memory::CIA1_SOURCE, self.cia1_timer.irq());
+        self.interrupts.set_nmi(C64Memory::CIA2_SOURCE, self.cia2_timer.irq());
     }
 
     pub fn screen_code_to_char(screen_code: u8) -> char{
@@ -310,6 +381,32 @@ impl C64Memory{
         }
     }
 
+    /// Start of the 16K the VIC fetches from, CIA2 port A selects it with its two low bits inverted.
+    pub fn vic_bank(&self) -> u16{
+        (3 - (self.cia2_port_a & 0x03) as u16) * 0x4000
+    }
+
+    /// The ROM byte that can be banked in at `address`, whether it is or not.
+    fn rom(&self, address: u16) -> Option<u8>{
+        match address {
+            0x8000 ..= 0x9fff => self.external_rom.as_ref().and_then(|rom| rom.get((address - 0x8000) as usize).copied()),
+            0xa000 ..= 0xbfff => Some(self.basic_rom[(address - 0xa000) as usize]),
+            0xd000 ..= 0xdfff => Some(self.character_rom[(address - 0xd000) as usize]),
+            0xe000 ..= 0xffff => Some(self.kernal[(address - 0xe000) as usize]),
+            _ => None,
+        }
+    }
+
+    fn rom_mut(&mut self, address: u16) -> Option<&mut u8>{
+        match address {
+            0x8000 ..= 0x9fff => self.external_rom.as_mut().and_then(|rom| rom.get_mut((address - 0x8000) as usize)),
+            0xa000 ..= 0xbfff => Some(&mut self.basic_rom[(address - 0xa000) as usize]),
+            0xd000 ..= 0xdfff => Some(&mut self.character_rom[(address - 0xd000) as usize]),
+            0xe000 ..= 0xffff => Some(&mut self.kernal[(address - 0xe000) as usize]),
+            _ => None,
+        }
+    }
+
     pub fn get_character_ram(&self) -> C64CharaterRam{
         let charram = self.ram[0x0400 .. 0x400+1000].try_into().unwrap();
         C64CharaterRam { ram: charram }
@@ -325,6 +422,7 @@ impl C64Memory{
         }
     }
 
+    #[allow(clippy::match_overlapping_arm)]
     pub fn write_io(&mut self, address: u16, value: u8){
         match address {
             0xd800 ..= 0xdbff => {
@@ -342,6 +440,7 @@ impl C64Memory{
                 }
             }
             0xdc00 => self.cia1_port_a = value,
+            0xdd00 => self.cia2_port_a = value,
             0xdc04 => {
                 self.cia1_timer.set_timer_a_low(value);
                 //println!("CIA1 Timer A low Write {}", value);
@@ -376,6 +475,7 @@ impl C64Memory{
             }
             0xdc0d => {
                 self.cia1_timer.set_timer_int(value);
+                self.update_interrupt_lines();
                 //println!("CIA1 INT Write {:#04x}", value);
             }
             0xdc0e => {
@@ -414,11 +514,11 @@ impl C64Memory{
 
     }
 
-    pub fn read_io(&mut self, address: u16) -> u8
-    {
+    /// IO registers as `read_io` returns them, without acknowledging interrupts or latching the time of day.
+    pub fn peek_io(&self, address: u16) -> u8{
         match address {
-            0xd011 => self.screen_control1,
-            0xd012 => 0x00, // Raster line
+            0xd011 => self.screen_control1 & 0x7f | (self.raster_line() >> 1) as u8 & 0x80,
+            0xd012 => self.raster_line() as u8,
             0xd016 => self.screen_control2,
             0xd020 => self.border_color,
             0xd021 => self.background_color,
@@ -437,6 +537,16 @@ impl C64Memory{
             0xdc05 => (self.cia1_timer.timer_a_counter >> 8) as u8,
             0xdc06 => (self.cia1_timer.timer_b_counter & 0xff) as u8,
             0xdc07 => (self.cia1_timer.timer_b_counter >> 8) as u8,
+            0xdc08 ..= 0xdc0b => self.cia1_timer.peek_time(address & 0x0f, self.cycles),
+            0xdc0d => self.cia1_timer.int_vec_read,
+            _ => 0x00,
+        }
+    }
+
+    #[allow(clippy::match_overlapping_arm)]
+    pub fn read_io(&mut self, address: u16) -> u8
+    {
+        match address {
             0xdc08 => {
                 let t = self.cia1_timer.get_tens();
                 println!("CIA1 Tens Read {}", t);
@@ -453,7 +563,7 @@ impl C64Memory{
                 m
             }
             0xdc0b => {
-                let h = self.cia1_timer.get_hour();
+                let h = self.cia1_timer.get_hour(self.cycles);
                 println!("CIA1 Hour Read {}", h);
                 h
             }
@@ -462,9 +572,11 @@ impl C64Memory{
             }
             0xdc0d => {
                 let r = self.cia1_timer.get_timer_int();
+                self.update_interrupt_lines();
                 //println!("CIA1 INT Read {:#04x}", r);
                 r
             }
+            0xd011 | 0xd012 | 0xd016 | 0xd020 | 0xd021 | 0xdc00 | 0xdc01 | 0xdc04 ..= 0xdc07 => self.peek_io(address),
             0xdc00 ..= 0xdc0f => {
                 //println!("CIA1 Read {:#06x}", address);
                 0x00
@@ -502,56 +614,16 @@ impl Memory6502 for C64Memory{
                 self.ram[address as usize] = value;
             }
         }
+        self.cycles += 1;
     }
 
     fn read_memory(&mut self, address: u16) -> u8 {
-        match address{
-            0x0000 => {
-                //println!("6510 DDR {:#06x}", address);
-                self.processor_port_ddr
-            },
-            0x0001 => {
-                //println!("6510 Port {:#06x}", address);
-                self.processor_port
-            },
-            0xa000 ..= 0xbfff => {
-                if (self.processor_port & 0x03) == 0x03 {
-                    self.basic_rom[(address - 0xa000) as usize]
-                } else {
-                    self.ram[address as usize]
-                }
-            },
-            0xd000 ..= 0xdfff => {
-                // Bit 2: CHAREN (0 = Char ROM, 1 = I/O)
-                // But if Bits 0-1 are 0, it's RAM regardless of CHAREN
-                if (self.processor_port & 0x03) == 0 {
-                    self.ram[address as usize]
-                } else if (self.processor_port & 0x04) != 0 {
-                    self.read_io(address)
-                } else {
-                    self.character_rom[(address - 0xd000) as usize]
-                }
-            },
-            0xe000 ..= 0xffff => {
-                if (self.processor_port & 0x02) != 0 {
-                    self.kernal[(address - 0xe000) as usize]
-                } else {
-                    self.ram[address as usize]
-                }
-            },
-            0x8000 ..= 0x9fff => {
-                let adr = address - 0x8000;
-                if let Some(ext_rom) = self.external_rom.as_ref(){
-                    ext_rom[adr as usize]
-                }
-                else{
-                    self.ram[adr as usize]
-                }
-            },
-            _ => {
-                self.ram[address as usize]
-            }
-        }
+        let value = match self.bank(address) {
+            Bank::Io if address > 0x0001 => self.read_io(address),
+            _ => self.peek(address),
+        };
+        self.cycles += 1;
+        value
     }
 
     fn read_memory_word(&mut self, address: u16) -> u16 {
@@ -560,6 +632,66 @@ impl Memory6502 for C64Memory{
 
         (hi as u16) << 8 | lo as u16
     }
+
+    fn peek(&self, address: u16) -> u8{
+        match (address, self.bank(address)) {
+            (0x0000, _) => self.processor_port_ddr,
+            (0x0001, _) => self.processor_port,
+            (_, Bank::Io) => self.peek_io(address),
+            (_, Bank::Rom) => self.peek_view(View::Rom, address),
+            (_, Bank::Ram) => self.ram[address as usize],
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u8){
+        self.write_memory(address, value);
+    }
+
+    /// The VIC view is the bank CIA2 selects, with the character ROM at $1000 in banks 0 and 2.
+    fn peek_view(&self, view: View, address: u16) -> u8{
+        match view {
+            View::Cpu => self.peek(address),
+            View::Ram => self.ram[address as usize],
+            View::Rom => self.rom(address).unwrap_or(self.ram[address as usize]),
+            View::Vic => {
+                let address = address & 0x3fff;
+                match (self.vic_bank(), address) {
+                    (0x0000 | 0x8000, 0x1000 ..= 0x1fff) => self.character_rom[(address - 0x1000) as usize],
+                    (bank, _) => self.ram[(bank | address) as usize],
+                }
+            }
+        }
+    }
+
+    /// Pokes into the ROM view patch the ROM images, the VIC view writes the RAM even under the character ROM.
+    fn poke_view(&mut self, view: View, address: u16, value: u8){
+        match view {
+            View::Cpu => self.poke(address, value),
+            View::Ram => self.ram[address as usize] = value,
+            View::Rom => match self.rom_mut(address) {
+                Some(byte) => *byte = value,
+                None => self.ram[address as usize] = value,
+            },
+            View::Vic => self.ram[(self.vic_bank() | address & 0x3fff) as usize] = value,
+        }
+    }
+
+    fn interrupt_controller(&self) -> Option<&InterruptController>{
+        Some(&self.interrupts)
+    }
+
+    fn bank(&self, address: u16) -> Bank{
+        match address{
+            0x0000 | 0x0001 => Bank::Io,
+            0xa000 ..= 0xbfff if (self.processor_port & 0x03) == 0x03 => Bank::Rom,
+            0xd000 ..= 0xdfff if (self.processor_port & 0x03) == 0 => Bank::Ram,
+            0xd000 ..= 0xdfff if (self.processor_port & 0x04) != 0 => Bank::Io,
+            0xd000 ..= 0xdfff => Bank::Rom,
+            0xe000 ..= 0xffff if (self.processor_port & 0x02) != 0 => Bank::Rom,
+            0x8000 ..= 0x9fff if self.external_rom.is_some() => Bank::Rom,
+            _ => Bank::Ram,
+        }
+    }
 }
 
 impl Memory6502Debug for C64Memory{
@@ -1,9 +1,12 @@
-mod cpu6502;
+pub use crate::cpu6502;
 pub mod c64memory;
-use cpu6502::{CPU6502,CpuError,InterruptType};
-use c64memory::{C64Memory,C64CharaterRam};
+use cpu6502::{CPU6502,InterruptType};
+pub use cpu6502::{CpuError,CpuRegisters};
+use c64memory::{C64Memory,C64CharaterRam,CYCLES_PER_LINE,LINES_PER_FRAME};
 
-use self::{c64memory::C64KeyboadMap, cpu6502::CPUState};
+use self::{c64memory::C64KeyboadMap, cpu6502::CPUState, cpu6502::breakpoints::Breakpoints, cpu6502::callstack::CallStack, cpu6502::trace::TraceSink};
+use cpu6502::stepping::{RunCommand, RunUntil};
+use cpu6502::memory::{Memory6502, View};
 
 pub struct C64{
     cpu: CPU6502,
@@ -11,16 +14,21 @@ pub struct C64{
 }
 
 impl C64{
+    /// Loads the ROMs from the roms directory.
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self{
-        let mem = C64Memory::new();
-        let cpu = CPU6502::new();
+        C64::with_memory(C64Memory::new())
+    }
 
-        C64 { cpu, memory: mem }
+    /// A machine around memory built elsewhere, e.g. `C64Memory::with_roms`, call `reset` before running it.
+    pub fn with_memory(memory: C64Memory) -> Self{
+        C64 { cpu: CPU6502::new(), memory }
     }
 
     pub fn reset(&mut self){
-        self.memory = C64Memory::new();
+        self.memory.reset();
         self.cpu.reset(&mut self.memory);
+        self.memory.set_cycles(self.cpu.cycles());
     }
 
     /*pub fn run(&mut self) -> Result<(), CpuError>{
@@ -30,12 +38,87 @@ impl C64{
     }*/
 
     pub fn run_single(&mut self) -> Result<u16, CpuError>{
-        let r = self.cpu.run_single(&mut self.memory)?;
-        let int = self.memory.tick();
-        if int{
-            self.interrupt();
+        let r = self.cpu.run_single(&mut self.memory);
+        self.tick_after(r)
+    }
+
+    /// `run_single` handing the executed instruction to `sink`, e.g. a `TraceLog`.
+    pub fn run_single_traced<SinkT: TraceSink + ?Sized>(&mut self, sink: &mut SinkT) -> Result<u16, CpuError>{
+        let r = self.cpu.run_single_traced(&mut self.memory, sink);
+        self.tick_after(r)
+    }
+
+    /// Runs until `command` is done, an error of any step ends it early, e.g. a breakpoint.
+    pub fn run_command<SinkT: TraceSink + ?Sized>(&mut self, command: RunCommand, sink: &mut SinkT) -> Result<u16, CpuError>{
+        let mut until = RunUntil::new(command, &self.registers(), self.cycles());
+        loop{
+            let pc = self.run_single_traced(&mut (&mut until, &mut *sink))?;
+            if until.is_done(&self.registers(), self.cycles()){
+                return Ok(pc);
+            }
+        }
+    }
+
+    /// Raster line the beam is on at the current cycle, the same one `$D012` reads.
+    pub fn raster_line(&self) -> u16{
+        self.memory.raster_line()
+    }
+
+    /// Cycles until the beam next starts `line`, `RunCommand::Cycles` of it runs there and line 0 is the next frame.
+    pub fn cycles_to_raster_line(&self, line: u16) -> u64{
+        let frame = CYCLES_PER_LINE * LINES_PER_FRAME;
+        let target = line as u64 % LINES_PER_FRAME * CYCLES_PER_LINE;
+        (target + frame - self.cycles() % frame - 1) % frame + 1
+    }
+
+    /// The devices see time pass unless nothing ran, a watchpoint stops after its instruction.
+    fn tick_after(&mut self, r: Result<u16, CpuError>) -> Result<u16, CpuError>{
+        self.memory.set_cycles(self.cpu.cycles());
+        if matches!(r, Ok(_) | Err(CpuError::Watchpoint { .. })){
+            self.memory.tick();
         }
-        Ok(r)
+        r
+    }
+
+    /// Breakpoints and watchpoints of the CPU, `run_single` returns `CpuError::Breakpoint` or `CpuError::Watchpoint` when one hits.
+    pub fn breakpoints(&self) -> &Breakpoints{
+        self.cpu.breakpoints()
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints{
+        self.cpu.breakpoints_mut()
+    }
+
+    /// The shadow call stack for backtraces, see `CallStack::write_backtrace`.
+    pub fn call_stack(&self) -> &CallStack{
+        self.cpu.call_stack()
+    }
+
+    /// Reads memory like the CPU without disturbing IO, for debuggers and tools.
+    pub fn peek(&self, address: u16) -> u8{
+        self.memory.peek(address)
+    }
+
+    /// Debug write, not seen by watchpoints.
+    pub fn poke(&mut self, address: u16, value: u8){
+        self.memory.poke(address, value);
+    }
+
+    /// `peek` into the CPU, VIC, RAM or ROM view.
+    pub fn peek_view(&self, view: View, address: u16) -> u8{
+        self.memory.peek_view(view, address)
+    }
+
+    pub fn poke_view(&mut self, view: View, address: u16, value: u8){
+        self.memory.poke_view(view, address, value);
+    }
+
+    pub fn registers(&self) -> CpuRegisters{
+        self.cpu.registers()
+    }
+
+    pub fn cycles(&self) -> u64{
+        self.cpu.cycles()
     }
 
     pub fn enable_trace(&mut self, trace_size_limit: usize){
@@ -51,6 +134,7 @@ impl C64{
         self.memory.show_stack();
     }
 
+    /// Forces an IRQ sequence right away, the CIAs raise theirs through the interrupt lines.
     pub fn interrupt(&mut self){
         //println!("INT");
         self.cpu.interrupt(InterruptType::INT, &mut self.memory);
@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use super::opcodes::{opcode_table, Opcode};
+use super::{AdressingType, CpuVariant};
+
+/// Error of `assemble`, `line` counts from 1.
+#[derive(Debug)]
+pub struct AssemblerError{
+    pub line: usize,
+    error_string: String,
+}
+
+impl AssemblerError{
+    fn new(error: &str, line: usize) -> Self{
+        AssemblerError { line, error_string: error.to_owned() }
+    }
+}
+
+impl std::fmt::Display for AssemblerError{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>{
+        fmt.write_str(&format!("[{} line={}]", self.error_string, self.line))
+    }
+}
+
+impl Error for AssemblerError{
+}
+
+/// Operand as written in the source, expressions are kept as text and evaluated in each pass.
+#[derive(Clone)]
+enum Operand{
+    None,
+    Accumulator,
+    Immediate(String),
+    Direct(String),
+    DirectX(String),
+    DirectY(String),
+    Indirect(String),
+    IndirectX(String),
+    IndirectY(String),
+    Pair(String, String),
+}
+
+enum Statement{
+    Empty,
+    Instruction(String, Operand),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+struct Line{
+    number: usize,
+    statement: Statement,
+    /// Addressing mode picked in the first pass, the second pass must emit the same size.
+    mode: Option<AdressingType>,
+}
+
+/// Assembles `source` into the bytes to place at `origin`.
+///
+/// One statement per line, `;` starts a comment. Lines may start with a `label:` and
+/// `name = expression` defines a constant. Besides instructions in the usual operand syntax
+/// `.byte` takes bytes and strings and `.word` takes little endian words. Expressions know
+/// `$hex`, `%binary`, decimal and `'c'` numbers, labels, `*` for the current address,
+/// `+ - * / & | ^ << >>`, parentheses and `<`/`>` for the low and high byte.
+pub fn assemble(source: &str, origin: u16, variant: CpuVariant) -> Result<Vec<u8>, AssemblerError>{
+    let table = opcode_table(variant);
+    let mut symbols = HashMap::new();
+    let mut lines = Vec::new();
+
+    // First pass: parse, define labels and decide every instruction size
+    let mut pc = origin as u32;
+    for (number, text) in source.lines().enumerate(){
+        let number = number + 1;
+        let text = strip_comment(text).trim();
+        let (label, rest) = split_label(text);
+        if let Some(label) = label{
+            define(&mut symbols, label, pc as i64, number)?;
+        }
+
+        if let Some((name, value)) = split_constant(rest){
+            let value = evaluate(value, &symbols, pc as u16).map_err(|e| AssemblerError::new(&e, number))?
+                .ok_or_else(|| AssemblerError::new("Constant uses an undefined symbol", number))?;
+            define(&mut symbols, name, value, number)?;
+            lines.push(Line { number, statement: Statement::Empty, mode: None });
+            continue;
+        }
+
+        let statement = parse_statement(rest).map_err(|e| AssemblerError::new(&e, number))?;
+        let mut mode = None;
+        pc += match &statement {
+            Statement::Empty => 0,
+            Statement::Bytes(items) => {
+                let mut size = 0;
+                for item in items{
+                    size += string_literal(item).map(|s| s.len()).unwrap_or(1) as u32;
+                }
+                size
+            }
+            Statement::Words(items) => 2 * items.len() as u32,
+            Statement::Instruction(mnemonic, operand) => {
+                let m = select_mode(table, mnemonic, operand, &symbols, pc as u16).map_err(|e| AssemblerError::new(&e, number))?;
+                mode = Some(m);
+                m.length() as u32
+            }
+        };
+        if pc > 0x10000{
+            return Err(AssemblerError::new("Program does not fit below $10000", number));
+        }
+        lines.push(Line { number, statement, mode });
+    }
+
+    // Second pass: every symbol is known, emit the bytes
+    let mut output = Vec::new();
+    for line in lines{
+        let pc = origin.overflowing_add(output.len() as u16).0;
+        let error = |e: String| AssemblerError::new(&e, line.number);
+        let value = |text: &str| -> Result<i64, AssemblerError>{
+            evaluate(text, &symbols, pc).map_err(error)?.ok_or_else(|| AssemblerError::new(&format!("Undefined symbol in '{}'", text), line.number))
+        };
+        match line.statement {
+            Statement::Empty => {}
+            Statement::Bytes(items) => {
+                for item in items{
+                    if let Some(s) = string_literal(&item){
+                        output.extend_from_slice(s.as_bytes());
+                    }
+                    else{
+                        output.push(to_byte(value(&item)?).map_err(error)?);
+                    }
+                }
+            }
+            Statement::Words(items) => {
+                for item in items{
+                    let word = to_word(value(&item)?).map_err(error)?;
+                    output.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            Statement::Instruction(mnemonic, operand) => {
+                let mode = line.mode.expect("mode chosen in the first pass");
+                let opcode = find_opcode(table, &mnemonic, mode).expect("opcode found in the first pass");
+                output.push(opcode);
+                let next = pc as i64 + mode.length() as i64;
+                match (mode, operand) {
+                    (AdressingType::Implied, _) | (AdressingType::Accumulator, _) => {}
+                    (AdressingType::Relative, Operand::Direct(target)) => {
+                        output.push(branch_offset(value(&target)?, next).map_err(error)?);
+                    }
+                    (AdressingType::ZeroPageRelative, Operand::Pair(zp, target)) => {
+                        output.push(to_byte(value(&zp)?).map_err(error)?);
+                        output.push(branch_offset(value(&target)?, next).map_err(error)?);
+                    }
+                    (_, Operand::Immediate(e)) | (_, Operand::Direct(e)) | (_, Operand::DirectX(e)) | (_, Operand::DirectY(e)) |
+                    (_, Operand::Indirect(e)) | (_, Operand::IndirectX(e)) | (_, Operand::IndirectY(e)) => {
+                        if mode.length() == 2{
+                            output.push(to_byte(value(&e)?).map_err(error)?);
+                        }
+                        else{
+                            output.extend_from_slice(&to_word(value(&e)?).map_err(error)?.to_le_bytes());
+                        }
+                    }
+                    _ => return Err(AssemblerError::new("Invalid operand", line.number)),
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn define(symbols: &mut HashMap<String, i64>, name: &str, value: i64, line: usize) -> Result<(), AssemblerError>{
+    if symbols.insert(name.to_ascii_lowercase(), value).is_some(){
+        return Err(AssemblerError::new(&format!("Symbol '{}' defined twice", name), line));
+    }
+    Ok(())
+}
+
+fn strip_comment(text: &str) -> &str{
+    let mut quote = None;
+    for (i, c) in text.char_indices(){
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ';') => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Removes whitespace except inside character and string literals.
+fn strip_spaces(text: &str) -> String{
+    let mut quote = None;
+    let mut stripped = String::with_capacity(text.len());
+    for c in text.chars(){
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, c) if c.is_whitespace() => continue,
+            _ => {}
+        }
+        stripped.push(c);
+    }
+    stripped
+}
+
+fn is_identifier(text: &str) -> bool{
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn split_label(text: &str) -> (Option<&str>, &str){
+    if let Some((label, rest)) = text.split_once(':'){
+        if is_identifier(label.trim()){
+            return (Some(label.trim()), rest.trim());
+        }
+    }
+    (None, text)
+}
+
+fn split_constant(text: &str) -> Option<(&str, &str)>{
+    let (name, value) = text.split_once('=')?;
+    if is_identifier(name.trim()){
+        Some((name.trim(), value.trim()))
+    }
+    else{
+        None
+    }
+}
+
+fn string_literal(text: &str) -> Option<&str>{
+    let text = text.trim();
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"'){
+        Some(&text[1..text.len() - 1])
+    }
+    else{
+        None
+    }
+}
+
+/// Splits on commas outside of quotes and parentheses.
+fn split_list(text: &str) -> Vec<String>{
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut current = String::new();
+    for c in text.chars(){
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    items.push(current.trim().to_owned());
+    items
+}
+
+/// True when the parenthesis at the start closes at the very end of `text`.
+fn wrapped_in_parentheses(text: &str) -> bool{
+    if !text.starts_with('(') || !text.ends_with(')'){
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in text.char_indices(){
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0{
+                    return i == text.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String>{
+    if text.is_empty(){
+        return Ok(Statement::Empty);
+    }
+    let (word, rest) = match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    };
+
+    match word.to_ascii_lowercase().as_str() {
+        ".byte" | ".db" => return Ok(Statement::Bytes(split_list(rest))),
+        ".word" | ".dw" => return Ok(Statement::Words(split_list(rest))),
+        w if w.starts_with('.') => return Err(format!("Unknown directive '{}'", word)),
+        _ => {}
+    }
+
+    let mnemonic = word.to_ascii_uppercase();
+    let operand = strip_spaces(rest);
+    let upper = operand.to_ascii_uppercase();
+    let operand = if operand.is_empty(){
+        Operand::None
+    }
+    else if upper == "A"{
+        Operand::Accumulator
+    }
+    else if let Some(e) = operand.strip_prefix('#'){
+        Operand::Immediate(e.to_owned())
+    }
+    else if upper.ends_with(",X)") && operand.starts_with('('){
+        Operand::IndirectX(operand[1..operand.len() - 3].to_owned())
+    }
+    else if upper.ends_with("),Y") && wrapped_in_parentheses(&operand[..operand.len() - 2]){
+        Operand::IndirectY(operand[1..operand.len() - 3].to_owned())
+    }
+    else if wrapped_in_parentheses(&operand){
+        Operand::Indirect(operand[1..operand.len() - 1].to_owned())
+    }
+    else if upper.ends_with(",X"){
+        Operand::DirectX(operand[..operand.len() - 2].to_owned())
+    }
+    else if upper.ends_with(",Y"){
+        Operand::DirectY(operand[..operand.len() - 2].to_owned())
+    }
+    else{
+        let items = split_list(&operand);
+        match items.as_slice() {
+            [zp, target] => Operand::Pair(zp.clone(), target.clone()),
+            _ => Operand::Direct(operand),
+        }
+    };
+    Ok(Statement::Instruction(mnemonic, operand))
+}
+
+/// Opcode for the mnemonic in the addressing mode, the documented one when undocumented opcodes duplicate it.
+fn find_opcode(table: &[Opcode; 256], mnemonic: &str, mode: AdressingType) -> Option<u8>{
+    if mnemonic == "NOP" && mode == AdressingType::Implied{
+        return Some(0xea);
+    }
+    (0..=255u8).find(|&i| table[i as usize].mnemonic == mnemonic && table[i as usize].mode == mode)
+}
+
+fn select_mode(table: &[Opcode; 256], mnemonic: &str, operand: &Operand, symbols: &HashMap<String, i64>, pc: u16) -> Result<AdressingType, String>{
+    let has = |mode: AdressingType| find_opcode(table, mnemonic, mode).is_some();
+    if !(0..=255u8).any(|i| table[i as usize].mnemonic == mnemonic){
+        return Err(format!("Unknown instruction '{}'", mnemonic));
+    }
+    // Zero page is only picked when the value is already known, forward references get the absolute form
+    let zero_page = |e: &str| -> Result<bool, String>{
+        Ok(matches!(evaluate(e, symbols, pc)?, Some(v) if (0..=0xff).contains(&v)))
+    };
+    let pick = |zero_page: bool, short: AdressingType, long: AdressingType|{
+        if (zero_page || !has(long)) && has(short) { short } else { long }
+    };
+
+    let mode = match operand {
+        Operand::None if has(AdressingType::Implied) => AdressingType::Implied,
+        Operand::None | Operand::Accumulator => AdressingType::Accumulator,
+        Operand::Immediate(_) => AdressingType::Immediate,
+        Operand::Direct(_) if has(AdressingType::Relative) => AdressingType::Relative,
+        Operand::Direct(e) => pick(zero_page(e)?, AdressingType::ZeroPage, AdressingType::Absolute),
+        Operand::DirectX(e) => pick(zero_page(e)?, AdressingType::ZeroPageX, AdressingType::AbsoluteX),
+        Operand::DirectY(e) => pick(zero_page(e)?, AdressingType::ZeroPageY, AdressingType::AbsoluteY),
+        Operand::Indirect(_) if has(AdressingType::Indirect) => AdressingType::Indirect,
+        Operand::Indirect(_) => AdressingType::ZeroPageIndirect,
+        Operand::IndirectX(_) if has(AdressingType::AbsoluteIndirectX) => AdressingType::AbsoluteIndirectX,
+        Operand::IndirectX(_) => AdressingType::IndirectX,
+        Operand::IndirectY(_) => AdressingType::IndirectY,
+        Operand::Pair(_, _) => AdressingType::ZeroPageRelative,
+    };
+
+    if has(mode){
+        Ok(mode)
+    }
+    else{
+        Err(format!("{} does not support {:?} addressing", mnemonic, mode))
+    }
+}
+
+fn to_byte(value: i64) -> Result<u8, String>{
+    if (-128..=255).contains(&value){
+        Ok(value as u8)
+    }
+    else{
+        Err(format!("Value {} does not fit in a byte", value))
+    }
+}
+
+fn to_word(value: i64) -> Result<u16, String>{
+    if (-32768..=65535).contains(&value){
+        Ok(value as u16)
+    }
+    else{
+        Err(format!("Value {} does not fit in a word", value))
+    }
+}
+
+fn branch_offset(target: i64, next: i64) -> Result<u8, String>{
+    let offset = target - next;
+    if (-128..=127).contains(&offset){
+        Ok(offset as u8)
+    }
+    else{
+        Err(format!("Branch target {:#06x} out of range", target))
+    }
+}
+
+/// Evaluates an expression, `Ok(None)` when it uses a symbol that is not defined yet.
+fn evaluate(text: &str, symbols: &HashMap<String, i64>, pc: u16) -> Result<Option<i64>, String>{
+    let mut parser = ExpressionParser { chars: strip_spaces(text).chars().collect(), pos: 0, symbols, pc };
+    let value = parser.binary(0)?;
+    if parser.pos != parser.chars.len(){
+        return Err(format!("Unexpected '{}' in expression '{}'", parser.chars[parser.pos], text));
+    }
+    Ok(value)
+}
+
+struct ExpressionParser<'a>{
+    chars: Vec<char>,
+    pos: usize,
+    symbols: &'a HashMap<String, i64>,
+    pc: u16,
+}
+
+impl ExpressionParser<'_>{
+    const OPERATORS: [&'static [&'static str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/"]];
+
+    fn peek(&self) -> Option<char>{
+        self.chars.get(self.pos).copied()
+    }
+
+    fn operator(&mut self, level: usize) -> Option<&'static str>{
+        for op in ExpressionParser::OPERATORS[level]{
+            let len = op.len();
+            if self.chars.len() >= self.pos + len && self.chars[self.pos..self.pos + len].iter().copied().eq(op.chars()){
+                self.pos += len;
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Option<i64>, String>{
+        if level == ExpressionParser::OPERATORS.len(){
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.operator(level){
+            let right = self.binary(level + 1)?;
+            left = match (left, right) {
+                (Some(l), Some(r)) => {
+                    let value = match op {
+                        "|" => Some(l | r),
+                        "^" => Some(l ^ r),
+                        "&" => Some(l & r),
+                        "<<" => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                        ">>" => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                        "+" => l.checked_add(r),
+                        "-" => l.checked_sub(r),
+                        "*" => l.checked_mul(r),
+                        _ if r == 0 => return Err("Division by zero".to_owned()),
+                        _ => l.checked_div(r),
+                    };
+                    Some(value.ok_or_else(|| format!("Overflow in '{}'", op))?)
+                }
+                _ => None,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Option<i64>, String>{
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                self.unary()?.map(|v| v.checked_neg().ok_or_else(|| "Overflow in '-'".to_owned())).transpose()
+            }
+            Some('<') => {
+                self.pos += 1;
+                Ok(self.unary()?.map(|v| v & 0xff))
+            }
+            Some('>') => {
+                self.pos += 1;
+                Ok(self.unary()?.map(|v| (v >> 8) & 0xff))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Option<i64>, String>{
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.binary(0)?;
+                if self.peek() != Some(')'){
+                    return Err("Missing ')'".to_owned());
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some('*') => {
+                self.pos += 1;
+                Ok(Some(self.pc as i64))
+            }
+            Some('\'') => {
+                match (self.chars.get(self.pos + 1), self.chars.get(self.pos + 2)) {
+                    (Some(&c), Some('\'')) => {
+                        self.pos += 3;
+                        Ok(Some(c as i64))
+                    }
+                    _ => Err("Bad character literal".to_owned()),
+                }
+            }
+            Some('$') => self.number(start + 1, 16),
+            Some('%') => self.number(start + 1, 2),
+            Some(c) if c.is_ascii_digit() => self.number(start, 10),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_'){
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                Ok(self.symbols.get(&name.to_ascii_lowercase()).copied())
+            }
+            Some(c) => Err(format!("Unexpected '{}' in expression", c)),
+            None => Err("Missing value".to_owned()),
+        }
+    }
+
+    fn number(&mut self, digits_start: usize, radix: u32) -> Result<Option<i64>, String>{
+        self.pos = digits_start;
+        while matches!(self.peek(), Some(c) if c.is_digit(radix)){
+            self.pos += 1;
+        }
+        let digits: String = self.chars[digits_start..self.pos].iter().collect();
+        i64::from_str_radix(&digits, radix).map(Some).map_err(|_| format!("Bad number '{}'", digits))
+    }
+}
@@ -0,0 +1,377 @@
+use std::ops::RangeInclusive;
+
+use super::memory::Memory6502;
+use super::CpuRegisters;
+
+/// What a breakpoint watches.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum WatchKind{
+    /// An instruction is about to run from the range.
+    Execute,
+    Read,
+    Write,
+    /// Read or write.
+    Access,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum Operand{
+    Register(char),
+    StackPointer,
+    ProgramCounter,
+    Memory(u16),
+    Value(u16),
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum Compare{
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+enum Expression{
+    Compare(Operand, Compare, Operand),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// Condition over registers and memory like `A==$20 && $D012>$80`.
+///
+/// Comparisons are `== != < <= > >=` joined with `&&`, `||` and parentheses. Operands are the registers
+/// A, X, Y, SP, PC and P, the flags N, V, B, D, I, Z and C (0 or 1) and numbers written `$d012`, `%1010` or `42`.
+/// A number on the left side of a comparison is the memory byte at that address, on the right side it is a value.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Condition{
+    expression: Expression,
+    text: String,
+}
+
+impl core::fmt::Display for Condition{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        fmt.write_str(&self.text)
+    }
+}
+
+struct ConditionParser<'a>{
+    text: &'a str,
+    pos: usize,
+}
+
+impl ConditionParser<'_>{
+    fn skip_spaces(&mut self){
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn take(&mut self, token: &str) -> bool{
+        self.skip_spaces();
+        if self.text[self.pos..].starts_with(token){
+            self.pos += token.len();
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Expression, String>{
+        let mut left = self.and()?;
+        while self.take("||"){
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, String>{
+        let mut left = self.compare()?;
+        while self.take("&&"){
+            left = Expression::And(Box::new(left), Box::new(self.compare()?));
+        }
+        Ok(left)
+    }
+
+    fn compare(&mut self) -> Result<Expression, String>{
+        if self.take("("){
+            let expression = self.or()?;
+            if !self.take(")"){
+                return Err("Missing ')'".to_owned());
+            }
+            return Ok(expression);
+        }
+        let left = self.operand(true)?;
+        let compare = [("==", Compare::Equal), ("!=", Compare::NotEqual), ("<=", Compare::LessEqual), (">=", Compare::GreaterEqual),
+            ("<", Compare::Less), (">", Compare::Greater), ("=", Compare::Equal)]
+            .into_iter().find(|(token, _)| self.take(token)).map(|(_, compare)| compare)
+            .ok_or_else(|| format!("Missing comparison at '{}'", &self.text[self.pos..]))?;
+        let right = self.operand(false)?;
+        Ok(Expression::Compare(left, compare, right))
+    }
+
+    fn operand(&mut self, left: bool) -> Result<Operand, String>{
+        self.skip_spaces();
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '$' && c != '%').unwrap_or(rest.len());
+        let token = &rest[..len];
+        self.pos += len;
+
+        let number = match token.as_bytes().first() {
+            Some(b'$') => u16::from_str_radix(&token[1..], 16).ok(),
+            Some(b'%') => u16::from_str_radix(&token[1..], 2).ok(),
+            Some(c) if c.is_ascii_digit() => token.parse().ok(),
+            _ => None,
+        };
+        if let Some(number) = number{
+            return Ok(if left { Operand::Memory(number) } else { Operand::Value(number) });
+        }
+        match token.to_ascii_uppercase().as_str() {
+            "SP" | "S" => Ok(Operand::StackPointer),
+            "PC" => Ok(Operand::ProgramCounter),
+            register @ ("A" | "X" | "Y" | "P" | "N" | "V" | "B" | "D" | "I" | "Z" | "C") => Ok(Operand::Register(register.chars().next().unwrap())),
+            "" => Err(format!("Missing operand at '{}'", rest)),
+            _ => Err(format!("Unknown operand '{}'", token)),
+        }
+    }
+}
+
+impl Condition{
+    pub fn parse(text: &str) -> Result<Condition, String>{
+        let mut parser = ConditionParser { text, pos: 0 };
+        let expression = parser.or()?;
+        parser.skip_spaces();
+        if parser.pos != text.len(){
+            return Err(format!("Unexpected '{}'", &text[parser.pos..]));
+        }
+        Ok(Condition { expression, text: text.trim().to_owned() })
+    }
+
+    /// Memory operands are read through `peek`, so IO registers are not disturbed.
+    pub fn eval<MemT: Memory6502 + ?Sized>(&self, registers: &CpuRegisters, memory: &MemT) -> bool{
+        Condition::eval_expression(&self.expression, registers, memory)
+    }
+
+    fn eval_expression<MemT: Memory6502 + ?Sized>(expression: &Expression, registers: &CpuRegisters, memory: &MemT) -> bool{
+        match expression {
+            Expression::And(left, right) => Condition::eval_expression(left, registers, memory) && Condition::eval_expression(right, registers, memory),
+            Expression::Or(left, right) => Condition::eval_expression(left, registers, memory) || Condition::eval_expression(right, registers, memory),
+            Expression::Compare(left, compare, right) => {
+                let left = Condition::value(*left, registers, memory);
+                let right = Condition::value(*right, registers, memory);
+                match compare {
+                    Compare::Equal => left == right,
+                    Compare::NotEqual => left != right,
+                    Compare::Less => left < right,
+                    Compare::LessEqual => left <= right,
+                    Compare::Greater => left > right,
+                    Compare::GreaterEqual => left >= right,
+                }
+            }
+        }
+    }
+
+    fn value<MemT: Memory6502 + ?Sized>(operand: Operand, registers: &CpuRegisters, memory: &MemT) -> u16{
+        let flag = |bit: u8| (registers.P.value() >> bit & 1) as u16;
+        match operand {
+            Operand::Register('A') => registers.A as u16,
+            Operand::Register('X') => registers.X as u16,
+            Operand::Register('Y') => registers.Y as u16,
+            Operand::Register('P') => registers.P.value() as u16,
+            Operand::Register('N') => flag(7),
+            Operand::Register('V') => flag(6),
+            Operand::Register('B') => flag(4),
+            Operand::Register('D') => flag(3),
+            Operand::Register('I') => flag(2),
+            Operand::Register('Z') => flag(1),
+            Operand::Register(_) => flag(0),
+            Operand::StackPointer => registers.SP as u16,
+            Operand::ProgramCounter => registers.PC,
+            Operand::Memory(address) => memory.peek(address) as u16,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+/// A breakpoint or watchpoint, stopping only when its condition holds.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Breakpoint{
+    pub kind: WatchKind,
+    pub range: RangeInclusive<u16>,
+    pub condition: Option<Condition>,
+    /// Removed after its first hit.
+    pub once: bool,
+    pub enabled: bool,
+    /// Times it stopped the CPU.
+    pub hits: u64,
+}
+
+impl Breakpoint{
+    /// Stops before the instruction at `pc`.
+    pub fn at(pc: u16) -> Self{
+        Breakpoint::watch(WatchKind::Execute, pc..=pc)
+    }
+
+    pub fn watch(kind: WatchKind, range: RangeInclusive<u16>) -> Self{
+        Breakpoint { kind, range, condition: None, once: false, enabled: true, hits: 0 }
+    }
+
+    pub fn with_condition(mut self, condition: Condition) -> Self{
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn temporary(mut self) -> Self{
+        self.once = true;
+        self
+    }
+
+    /// Reads `[once] [exec|read|write|access] <address>[-<address>] [if <condition>]`,
+    /// e.g. `$e5cd`, `once $0810 if X==0` or `write $d020-$d021 if A!=0`.
+    pub fn parse(text: &str) -> Result<Breakpoint, String>{
+        let (text, condition) = match text.split_once(" if ") {
+            Some((text, condition)) => (text, Some(Condition::parse(condition)?)),
+            None => (text, None),
+        };
+        let mut words: Vec<&str> = text.split_whitespace().collect();
+        let once = words.first() == Some(&"once");
+        if once{
+            words.remove(0);
+        }
+        let kind = match words.first().copied() {
+            Some("exec") => Some(WatchKind::Execute),
+            Some("read") => Some(WatchKind::Read),
+            Some("write") => Some(WatchKind::Write),
+            Some("access") => Some(WatchKind::Access),
+            _ => None,
+        };
+        if kind.is_some(){
+            words.remove(0);
+        }
+        let [range] = words[..] else {
+            return Err(format!("Expected one address or range in '{}'", text));
+        };
+        let address = |text: &str| u16::from_str_radix(text.trim_start_matches('$'), 16).map_err(|_| format!("Bad address '{}'", text));
+        let range = match range.split_once('-') {
+            Some((start, end)) => address(start)?..=address(end)?,
+            None => address(range)?..=address(range)?,
+        };
+
+        let mut breakpoint = Breakpoint::watch(kind.unwrap_or(WatchKind::Execute), range);
+        breakpoint.condition = condition;
+        breakpoint.once = once;
+        Ok(breakpoint)
+    }
+
+    fn watches(&self, address: u16, write: bool) -> bool{
+        self.enabled && self.range.contains(&address) && match self.kind {
+            WatchKind::Execute => false,
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+/// Breakpoints and watchpoints of a CPU, numbered in the order they were added.
+#[derive(Clone,Debug,Default)]
+pub struct Breakpoints{
+    entries: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    last_hit: Option<usize>,
+    /// The instruction the last breakpoint stopped before, it runs when execution resumes.
+    resume_at: Option<u16>,
+    /// Data accesses of the current instruction that hit a watched range.
+    accesses: Vec<(u16, u8, bool)>,
+}
+
+impl Breakpoints{
+    /// Returns the id for `get` and `remove`.
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize{
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Breakpoint>{
+        let index = self.entries.iter().position(|(entry, _)| *entry == id)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint>{
+        self.entries.iter().find(|(entry, _)| *entry == id).map(|(_, breakpoint)| breakpoint)
+    }
+
+    /// To enable, disable or change a breakpoint.
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Breakpoint>{
+        self.entries.iter_mut().find(|(entry, _)| *entry == id).map(|(_, breakpoint)| breakpoint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Breakpoint)>{
+        self.entries.iter().map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self){
+        self.entries.clear();
+        self.resume_at = None;
+        self.accesses.clear();
+    }
+
+    /// Id of the breakpoint that stopped the CPU last, also when it was a temporary one that is gone now.
+    pub fn last_hit(&self) -> Option<usize>{
+        self.last_hit
+    }
+
+    fn hit(&mut self, index: usize){
+        let (id, breakpoint) = &mut self.entries[index];
+        breakpoint.hits += 1;
+        self.last_hit = Some(*id);
+        if breakpoint.once{
+            self.entries.remove(index);
+        }
+    }
+
+    /// Whether to stop before the instruction at `registers.PC`. The instruction stopped at last time is let through once.
+    pub(crate) fn check_execute<MemT: Memory6502 + ?Sized>(&mut self, registers: &CpuRegisters, memory: &MemT) -> bool{
+        self.accesses.clear();
+        if self.resume_at.take() == Some(registers.PC){
+            return false;
+        }
+        let index = self.entries.iter().position(|(_, breakpoint)| {
+            breakpoint.enabled && breakpoint.kind == WatchKind::Execute && breakpoint.range.contains(&registers.PC)
+                && breakpoint.condition.as_ref().is_none_or(|condition| condition.eval(registers, memory))
+        });
+        if let Some(index) = index{
+            self.hit(index);
+            self.resume_at = Some(registers.PC);
+            return true;
+        }
+        false
+    }
+
+    /// Called for every data access on the bus.
+    pub(crate) fn access(&mut self, address: u16, value: u8, write: bool){
+        if self.entries.iter().any(|(_, breakpoint)| breakpoint.watches(address, write)){
+            self.accesses.push((address, value, write));
+        }
+    }
+
+    /// The first access of the finished instruction a watchpoint stops for.
+    pub(crate) fn check_accesses<MemT: Memory6502 + ?Sized>(&mut self, registers: &CpuRegisters, memory: &MemT) -> Option<(u16, u8, bool)>{
+        let entries = &self.entries;
+        let found = self.accesses.iter().find_map(|&(address, value, write)| {
+            entries.iter().position(|(_, breakpoint)| {
+                breakpoint.watches(address, write) && breakpoint.condition.as_ref().is_none_or(|condition| condition.eval(registers, memory))
+            }).map(|index| (index, address, value, write))
+        });
+        self.accesses.clear();
+        let (index, address, value, write) = found?;
+        self.hit(index);
+        Some((address, value, write))
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{self, Write};
+
+use super::symbols::Symbols;
+use super::{CPUState, InterruptType};
+
+/// How a frame was entered.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum FrameKind{
+    Jsr,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl FrameKind{
+    fn name(self) -> &'static str{
+        match self {
+            FrameKind::Jsr => "JSR",
+            FrameKind::Brk => "BRK",
+            FrameKind::Irq => "IRQ",
+            FrameKind::Nmi => "NMI",
+        }
+    }
+}
+
+/// One entry of the shadow call stack.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Frame{
+    pub kind: FrameKind,
+    /// Subroutine or interrupt handler address.
+    pub function: u16,
+    /// Address of the JSR or BRK, or of the instruction the interrupt came before.
+    pub call_site: u16,
+    /// Where execution continues when the frame returns.
+    pub return_address: u16,
+    /// SP right after the return address was pushed.
+    pub sp: u8,
+}
+
+/// Most frames kept, deeper stacks drop their oldest frames.
+const MAX_DEPTH: usize = 256;
+
+/// Follows JSR, BRK and interrupts through the executed instructions.
+/// Frames are dropped when SP rises above where their return address was pushed, so RTS, RTI, TXS and
+/// PLA/PLA tricks that throw away return addresses all unwind it, while a PHA/PHA/RTS jump leaves it as it is.
+#[derive(Clone,Debug,Default)]
+pub struct CallStack{
+    frames: Vec<Frame>,
+}
+
+impl CallStack{
+    pub fn new() -> Self{
+        CallStack { frames: Vec::new() }
+    }
+
+    /// Outermost frame first.
+    pub fn frames(&self) -> &[Frame]{
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize{
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self){
+        self.frames.clear();
+    }
+
+    /// Applies one executed instruction or interrupt sequence, returns true when frames were pushed or dropped.
+    pub fn update(&mut self, state: &CPUState) -> bool{
+        let sp = state.registers().SP;
+        let depth = self.frames.len();
+        while self.frames.last().is_some_and(|frame| frame.sp < sp){
+            self.frames.pop();
+        }
+
+        let call_site = state.registers_before().PC;
+        let (kind, return_address) = match (state.interrupt(), state.opcode()) {
+            (Some(InterruptType::NMI), _) => (FrameKind::Nmi, call_site),
+            (Some(_), _) => (FrameKind::Irq, call_site),
+            (None, 0x20) => (FrameKind::Jsr, call_site.overflowing_add(3).0),
+            (None, 0x00) => (FrameKind::Brk, call_site.overflowing_add(2).0),
+            _ => return self.frames.len() != depth,
+        };
+        if self.frames.len() == MAX_DEPTH{
+            self.frames.remove(0);
+        }
+        self.frames.push(Frame { kind, function: state.address(), call_site, return_address, sp });
+        true
+    }
+
+    /// Innermost frame first, starting with `pc` where execution is now:
+    ///
+    /// `#1 JSR 0610 inner      from 060A sub        to 060D sub+3`
+    pub fn write_backtrace<W: Write>(&self, out: &mut W, pc: u16, symbols: &Symbols) -> io::Result<()>{
+        writeln!(out, "#0      {:04X} {}", pc, symbols.describe(pc))?;
+        for (i, frame) in self.frames.iter().rev().enumerate(){
+            writeln!(out, "#{:<2} {} {:04X} {:<16} from {:04X} {:<16} to {:04X} {}", i + 1, frame.kind.name(), frame.function,
+                symbols.describe(frame.function), frame.call_site, symbols.describe(frame.call_site), frame.return_address,
+                symbols.describe(frame.return_address))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use super::memory::{Bank, Memory6502};
+use super::trace::TraceSink;
+use super::{Access, CPUState, CycleKind};
+
+/// An opcode was fetched from the byte.
+pub const OPCODE: u8 = 0x01;
+/// The byte was fetched as an operand.
+pub const OPERAND: u8 = 0x02;
+/// A load or read-modify-write read the byte.
+pub const READ: u8 = 0x04;
+/// The byte was written, by a store, a read-modify-write or a push.
+pub const WRITE: u8 = 0x08;
+
+const BANKS: [Bank; 3] = [Bank::Ram, Bank::Rom, Bank::Io];
+
+/// Size of the raw bitmap, one flag byte per address for RAM, ROM and IO in that order.
+pub const BITMAP_SIZE: usize = BANKS.len() * 0x10000;
+
+fn bank_index(bank: Bank) -> usize{
+    match bank {
+        Bank::Ram => 0,
+        Bank::Rom => 1,
+        Bank::Io => 2,
+    }
+}
+
+/// Bytes of a range with each kind of access.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct CoverageSummary{
+    pub bytes: usize,
+    pub opcodes: usize,
+    pub operands: usize,
+    pub reads: usize,
+    pub writes: usize,
+    /// Bytes with any access.
+    pub touched: usize,
+}
+
+/// Marks what happened to every address, separately for RAM, ROM and IO so the C64 overlays do not mix.
+/// Feed it through `run_single_traced`. Reads are the effective addresses of loads and read-modify-writes,
+/// pointers and pulls are not marked. Writes are every data write on the bus, dummy writes left out.
+/// The bank is the one mapped when the bus cycle happened.
+#[derive(Clone)]
+pub struct Coverage{
+    flags: Vec<u8>,
+}
+
+impl Default for Coverage{
+    fn default() -> Self{
+        Coverage::new()
+    }
+}
+
+impl Coverage{
+    pub fn new() -> Self{
+        Coverage { flags: vec![0; BITMAP_SIZE] }
+    }
+
+    /// `OPCODE`, `OPERAND`, `READ` and `WRITE` bits of an address.
+    pub fn get(&self, bank: Bank, address: u16) -> u8{
+        self.flags[bank_index(bank) * 0x10000 + address as usize]
+    }
+
+    pub fn mark(&mut self, bank: Bank, address: u16, flags: u8){
+        self.flags[bank_index(bank) * 0x10000 + address as usize] |= flags;
+    }
+
+    /// Adds the accesses of another run.
+    pub fn merge(&mut self, other: &Coverage){
+        for (flags, other) in self.flags.iter_mut().zip(other.flags.iter()){
+            *flags |= other;
+        }
+    }
+
+    pub fn summary(&self, bank: Bank, range: RangeInclusive<u16>) -> CoverageSummary{
+        let mut summary = CoverageSummary::default();
+        for address in range{
+            let flags = self.get(bank, address);
+            summary.bytes += 1;
+            summary.opcodes += (flags & OPCODE != 0) as usize;
+            summary.operands += (flags & OPERAND != 0) as usize;
+            summary.reads += (flags & READ != 0) as usize;
+            summary.writes += (flags & WRITE != 0) as usize;
+            summary.touched += (flags != 0) as usize;
+        }
+        summary
+    }
+
+    /// Runs of addresses with one of `flags`, e.g. `OPCODE | OPERAND` for executed code.
+    pub fn ranges(&self, bank: Bank, flags: u8) -> Vec<RangeInclusive<u16>>{
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for address in 0..=0xffff_u16{
+            match (self.get(bank, address) & flags != 0, start) {
+                (true, None) => start = Some(address),
+                (false, Some(first)) => {
+                    ranges.push(first..=address - 1);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start{
+            ranges.push(first..=0xffff);
+        }
+        ranges
+    }
+
+    /// Every run of touched addresses per bank with its counts.
+    pub fn write_report<W: Write>(&self, out: &mut W) -> io::Result<()>{
+        writeln!(out, "{:<4} {:<9} {:>7} {:>7} {:>7} {:>7} {:>7}", "bank", "range", "bytes", "opcode", "operand", "read", "write")?;
+        for bank in BANKS{
+            for range in self.ranges(bank, OPCODE | OPERAND | READ | WRITE){
+                let summary = self.summary(bank, range.clone());
+                writeln!(out, "{:<4} {:04X}-{:04X} {:>7} {:>7} {:>7} {:>7} {:>7}", format!("{:?}", bank).to_uppercase(),
+                    range.start(), range.end(), summary.bytes, summary.opcodes, summary.operands, summary.reads, summary.writes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Raw bitmap of `BITMAP_SIZE` bytes, files of several runs merge by OR-ing them.
+    pub fn write_bitmap<W: Write>(&self, out: &mut W) -> io::Result<()>{
+        out.write_all(&self.flags)
+    }
+
+    pub fn read_bitmap<R: Read>(input: &mut R) -> io::Result<Coverage>{
+        let mut flags = Vec::with_capacity(BITMAP_SIZE);
+        input.read_to_end(&mut flags)?;
+        if flags.len() != BITMAP_SIZE{
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("coverage bitmap is {} bytes, expected {}", flags.len(), BITMAP_SIZE)));
+        }
+        Ok(Coverage { flags })
+    }
+}
+
+impl TraceSink for Coverage{
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502){
+        let cycles = state.bus_cycles();
+        // The bank the byte was accessed through, bytes that never went over the bus fall back to the current one
+        let bank_at = |address, write| cycles.iter().find(|cycle| cycle.address == address && cycle.write == write)
+            .map_or_else(|| memory.bank(address), |cycle| cycle.bank);
+        for cycle in cycles{
+            if cycle.write && cycle.kind == CycleKind::Data{
+                // Writes under a ROM end up in the RAM below it
+                let bank = if cycle.bank == Bank::Rom { Bank::Ram } else { cycle.bank };
+                self.mark(bank, cycle.address, WRITE);
+            }
+        }
+        if state.interrupt().is_some(){
+            return;
+        }
+        let instruction = state.disassemble();
+        for i in 0..instruction.length{
+            let address = instruction.address.overflowing_add(i as u16).0;
+            self.mark(bank_at(address, false), address, if i == 0 { OPCODE } else { OPERAND });
+        }
+
+        if matches!(state.access(), Some(Access::Read | Access::Modify)){
+            self.mark(bank_at(state.address(), false), state.address(), READ);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(feature = "std")]
+use std::ops::RangeInclusive;
+
+use super::memory::Memory6502;
+use super::opcodes::opcode_table;
+use super::{AdressingType, CpuVariant};
+
+/// One decoded instruction.
+#[derive(Clone,Copy,Debug)]
+pub struct Instruction{
+    pub address: u16,
+    /// Opcode followed by the operand bytes, only the first `length` bytes are used.
+    pub bytes: [u8; 3],
+    pub length: u8,
+    pub mnemonic: &'static str,
+    /// Where a branch, JMP or JSR goes when its destination is known without running it.
+    pub target: Option<u16>,
+    mode: AdressingType,
+}
+
+impl Instruction{
+    /// Decodes the instruction made of `bytes` located at `address`.
+    pub fn decode(address: u16, bytes: [u8; 3], variant: CpuVariant) -> Self{
+        let opcode = &opcode_table(variant)[bytes[0] as usize];
+        let length = opcode.mode.length();
+        let next = address.overflowing_add(length as u16).0;
+        let word = (bytes[2] as u16) << 8 | bytes[1] as u16;
+        let target = match opcode.mode {
+            AdressingType::Relative => Some(next.overflowing_add(bytes[1] as i8 as u16).0),
+            AdressingType::ZeroPageRelative => Some(next.overflowing_add(bytes[2] as i8 as u16).0),
+            AdressingType::Absolute if bytes[0] == 0x4c || bytes[0] == 0x20 => Some(word), //JMP and JSR
+            _ => None,
+        };
+
+        Instruction { address, bytes, length, mnemonic: opcode.mnemonic, target, mode: opcode.mode }
+    }
+
+    /// Operand in the usual assembler syntax, empty for implied instructions.
+    #[cfg(feature = "std")]
+    pub fn operand(&self) -> String{
+        let mut operand = String::new();
+        self.write_operand(&mut operand).expect("writing to a String");
+        operand
+    }
+
+    fn write_operand<W: core::fmt::Write>(&self, out: &mut W) -> core::fmt::Result{
+        let byte = self.bytes[1];
+        let word = (self.bytes[2] as u16) << 8 | self.bytes[1] as u16;
+        let target = self.target.unwrap_or(0);
+        match self.mode {
+            AdressingType::Implied => Ok(()),
+            AdressingType::Accumulator => out.write_str("A"),
+            AdressingType::Immediate => write!(out, "#${:02x}", byte),
+            AdressingType::ZeroPage => write!(out, "${:02x}", byte),
+            AdressingType::ZeroPageX => write!(out, "${:02x},X", byte),
+            AdressingType::ZeroPageY => write!(out, "${:02x},Y", byte),
+            AdressingType::Absolute => write!(out, "${:04x}", word),
+            AdressingType::AbsoluteX => write!(out, "${:04x},X", word),
+            AdressingType::AbsoluteY => write!(out, "${:04x},Y", word),
+            AdressingType::Indirect => write!(out, "(${:04x})", word),
+            AdressingType::IndirectX => write!(out, "(${:02x},X)", byte),
+            AdressingType::IndirectY => write!(out, "(${:02x}),Y", byte),
+            AdressingType::ZeroPageIndirect => write!(out, "(${:02x})", byte),
+            AdressingType::AbsoluteIndirectX => write!(out, "(${:04x},X)", word),
+            AdressingType::Relative => write!(out, "${:04x}", target),
+            AdressingType::ZeroPageRelative => write!(out, "${:02x},${:04x}", byte, target),
+        }
+    }
+
+    /// Address, raw bytes and the instruction, as in a monitor listing.
+    #[cfg(feature = "std")]
+    pub fn listing(&self) -> String{
+        let bytes: Vec<String> = self.bytes[..self.length as usize].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{:04x}  {:<8}  {}", self.address, bytes.join(" "), self)
+    }
+}
+
+impl core::fmt::Display for Instruction{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        fmt.write_str(self.mnemonic)?;
+        if self.mode != AdressingType::Implied{
+            fmt.write_str(" ")?;
+            self.write_operand(fmt)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction at `address`, reading only the bytes it is made of.
+pub fn disassemble<MemT: Memory6502 + ?Sized>(memory: &MemT, address: u16, variant: CpuVariant) -> Instruction{
+    let mut bytes = [memory.peek(address), 0, 0];
+    let length = opcode_table(variant)[bytes[0] as usize].mode.length();
+    for (i, byte) in bytes.iter_mut().enumerate().take(length as usize).skip(1){
+        *byte = memory.peek(address.overflowing_add(i as u16).0);
+    }
+    Instruction::decode(address, bytes, variant)
+}
+
+/// Decodes every instruction starting inside `range`, the last one may extend past its end.
+#[cfg(feature = "std")]
+pub fn disassemble_range<MemT: Memory6502 + ?Sized>(memory: &MemT, range: RangeInclusive<u16>, variant: CpuVariant) -> Vec<Instruction>{
+    let mut instructions = Vec::new();
+    let mut address = *range.start() as u32;
+    while address <= *range.end() as u32{
+        let instruction = disassemble(memory, address as u16, variant);
+        address += instruction.length as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
@@ -0,0 +1,66 @@
+/// IRQ and NMI lines shared by the devices on the bus.
+/// Both lines are wired-OR, every device pulls its own source low and the line stays low while any source does.
+/// IRQ is level-triggered, NMI is edge-triggered, the CPU samples both on every bus cycle.
+/// Sources are numbered 0-31, a higher one panics.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct InterruptController{
+    irq: u32,
+    nmi: u32,
+}
+
+impl InterruptController{
+    pub fn new() -> Self{
+        InterruptController::default()
+    }
+
+    /// Pulls IRQ low for `source` (0-31).
+    pub fn assert_irq(&mut self, source: u8){
+        self.irq |= source_bit(source);
+    }
+
+    pub fn release_irq(&mut self, source: u8){
+        self.irq &= !source_bit(source);
+    }
+
+    pub fn set_irq(&mut self, source: u8, asserted: bool){
+        if asserted{
+            self.assert_irq(source);
+        }
+        else{
+            self.release_irq(source);
+        }
+    }
+
+    /// Pulls NMI low for `source` (0-31), only the first source to do so makes an edge.
+    pub fn assert_nmi(&mut self, source: u8){
+        self.nmi |= source_bit(source);
+    }
+
+    pub fn release_nmi(&mut self, source: u8){
+        self.nmi &= !source_bit(source);
+    }
+
+    pub fn set_nmi(&mut self, source: u8, asserted: bool){
+        if asserted{
+            self.assert_nmi(source);
+        }
+        else{
+            self.release_nmi(source);
+        }
+    }
+
+    /// True while any source holds IRQ low.
+    pub fn irq(&self) -> bool{
+        self.irq != 0
+    }
+
+    /// True while any source holds NMI low.
+    pub fn nmi(&self) -> bool{
+        self.nmi != 0
+    }
+}
+
+fn source_bit(source: u8) -> u32{
+    assert!(source < 32, "Interrupt source {} out of range 0-31", source);
+    1 << source
+}
@@ -0,0 +1,208 @@
+use super::interrupts::InterruptController;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::prelude::*;
+
+/// What answers the CPU at an address.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum Bank{
+    #[default]
+    Ram,
+    Rom,
+    Io,
+}
+
+/// Address space seen through `peek_view` and `poke_view`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum View{
+    /// What the CPU sees with the current banking.
+    Cpu,
+    /// The 16K the video chip fetches from, addresses wrap at $4000.
+    Vic,
+    /// RAM, also where ROM or IO is banked in over it.
+    Ram,
+    /// ROM images whether banked in or not, RAM where there is no ROM.
+    Rom,
+}
+
+pub trait Memory6502 {
+    fn write_memory(&mut self, address: u16, value: u8);
+    fn read_memory(&mut self, address: u16) -> u8;
+    fn read_memory_word(&mut self, address: u16) -> u16;
+
+    /// Reads what the CPU would read without side effects, IO registers are not acknowledged or latched and
+    /// no bus fault is reported. Debuggers, traces and other tools read memory through this.
+    fn peek(&self, address: u16) -> u8;
+
+    /// Debug write, lands where a CPU write would without being seen by the CPU, e.g. by watchpoints.
+    fn poke(&mut self, address: u16, value: u8);
+
+    fn peek_word(&self, address: u16) -> u16{
+        u16::from_le_bytes([self.peek(address), self.peek(address.overflowing_add(1).0)])
+    }
+
+    /// `peek` into one of the views, memory without banking has the same bytes in all of them.
+    fn peek_view(&self, _view: View, address: u16) -> u8{
+        self.peek(address)
+    }
+
+    fn poke_view(&mut self, _view: View, address: u16, value: u8){
+        self.poke(address, value)
+    }
+
+    /// Address of an access that hit nothing since the last call, the CPU reports it as a bus fault.
+    fn take_bus_fault(&mut self) -> Option<u16>{
+        None
+    }
+
+    /// IRQ and NMI lines of the devices behind this bus, sampled by the CPU after every access.
+    fn interrupt_controller(&self) -> Option<&InterruptController>{
+        None
+    }
+
+    /// RDY input, while it is low the CPU stalls before its next read cycle (and write cycle on the 65C02).
+    /// Asked again for every stalled cycle so the device sees time pass.
+    fn rdy(&mut self) -> bool{
+        true
+    }
+
+    /// SO input, true while the pin is held low. Its falling edge sets V.
+    fn so(&self) -> bool{
+        false
+    }
+
+    /// What is mapped at `address` for the CPU right now, used by tools to tell ROM from RAM.
+    fn bank(&self, _address: u16) -> Bank{
+        Bank::Ram
+    }
+}
+
+pub trait Memory6502Debug {
+    fn show_stack(&self);
+    fn show_zero_page(&self);
+}
+
+/// Flat RAM for tests and tools, accesses past its end read 0 and are reported as bus faults.
+#[cfg(feature = "std")]
+pub struct Memory{
+    memory: Vec<u8>,
+    fault: Option<u16>,
+}
+
+#[cfg(feature = "std")]
+impl Memory {
+    pub fn new(size: usize) -> Self{
+        Memory{ memory: vec![0; size], fault: None}
+    }
+
+    pub fn from_file(file: &str) -> std::io::Result<Self>{
+        let mut file = File::open(file)?;
+        let file_size = file.metadata()?.len();
+        let mut data = Vec::with_capacity(file_size as usize);
+        file.read_to_end(&mut data)?;
+        Ok(Memory{memory: data, fault: None})
+    }
+}
+
+#[cfg(feature = "std")]
+impl Memory6502Debug for Memory {
+    fn show_stack(&self){
+        let mslicee: [u8; 16] = self.memory[0x01f0 .. 0x0200].try_into().unwrap();
+        println!("{:04x}: {:02x?}", 0x01f0, mslicee);
+    }
+
+    fn show_zero_page(&self){
+        let mut last = [0xff; 16];
+        let mut lasti = 0;
+
+        for i in 0..256/16{
+            let mslicee: [u8; 16] = self.memory[i*16 .. (i+1)*16].try_into().unwrap();
+
+            if mslicee != last{
+                if lasti+1 != i && i != 0{
+                    println!("*");
+                }
+                println!("{:04x}: {:02x?}", i*16, mslicee);
+                lasti = i;
+            }
+            else if i == 256/16-1 {
+                println!("*\n{:04x}", i*16);
+            }
+            last = mslicee;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Memory6502 for Memory{
+    fn write_memory(&mut self, address: u16, value: u8){
+        if let Some(mem) = self.memory.get_mut(address as usize){
+            *mem = value;
+        }
+        else{
+            self.fault.get_or_insert(address);
+        }
+    }
+
+    fn read_memory(&mut self, address: u16) -> u8{
+        if let Some(mem) = self.memory.get(address as usize){
+            *mem
+        }
+        else
+        {
+            self.fault.get_or_insert(address);
+            0
+        }
+    }
+
+    fn read_memory_word(&mut self, address: u16) -> u16{
+        let lo = self.read_memory(address);
+        let hi = self.read_memory(address.overflowing_add(1).0);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn peek(&self, address: u16) -> u8{
+        self.memory.get(address as usize).copied().unwrap_or(0)
+    }
+
+    fn poke(&mut self, address: u16, value: u8){
+        if let Some(mem) = self.memory.get_mut(address as usize){
+            *mem = value;
+        }
+    }
+
+    fn take_bus_fault(&mut self) -> Option<u16>{
+        self.fault.take()
+    }
+
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Debug for Memory {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>{
+        let l = self.memory.len() / 16;
+
+        let mut last = [0xff; 16];
+        let mut lasti = 0;
+
+        for i in 0..l{
+            let mslicee: [u8; 16] = self.memory[i*16 .. (i+1)*16].try_into().unwrap();
+
+            if mslicee != last{
+                if lasti+1 != i && i != 0{
+                    fmt.write_str("*\n")?;
+                }
+                fmt.write_str(&format!("{:04x}: {:02x?}\n", i*16, mslicee))?;
+                lasti = i;
+            }
+            else if i == l-1 {
+                fmt.write_str(&format!("*\n{:04x}\n", i*16))?;
+            }
+
+            last = mslicee;
+        }
+
+        fmt.write_str("")
+    }
+}
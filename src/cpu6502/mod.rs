@@ -0,0 +1,3333 @@
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod breakpoints;
+#[cfg(feature = "std")]
+pub mod callstack;
+#[cfg(feature = "std")]
+pub mod coverage;
+pub mod disassembler;
+pub mod interrupts;
+pub mod memory;
+mod opcodes;
+#[cfg(feature = "std")]
+pub mod profiler;
+pub mod stepping;
+#[cfg(feature = "std")]
+pub mod symbols;
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod trace_diff;
+
+use self::disassembler::Instruction;
+use self::memory::{Bank, Memory6502};
+use self::opcodes::Operation;
+use self::stepping::{RunCommand, RunUntil};
+use self::trace::TraceSink;
+
+/// Processor status P, bit 7 to 0: N V - B D I Z C.
+#[derive(Clone,Copy,PartialEq,Eq)]
+pub struct StatusRegister{
+    value: u8,
+}
+
+#[allow(non_snake_case)]
+impl StatusRegister {
+    pub fn new(value: u8) -> Self{
+        StatusRegister { value }
+    }
+
+    /// All eight bits as PHP would push them, without forcing B.
+    pub fn value(&self) -> u8{
+        self.value
+    }
+
+    pub fn set_value(&mut self, value: u8){
+        self.value = value;
+    }
+
+    fn set_NZ(&mut self, val: u8){
+        self.value = (self.value & 0b0111_1111) | (val & 0b1000_0000);
+        self.value = (self.value & 0b1111_1101) | ((val == 0) as u8) << 1;
+    }
+
+    pub fn set_N(&mut self, val: bool){
+        self.value = (self.value & 0b0111_1111) | (val as u8) << 7;
+    }
+
+    pub fn set_Z(&mut self, val: bool){
+        self.value = (self.value & 0b1111_1101) | (val as u8) << 1;
+    }
+
+    pub fn set_C(&mut self, val: bool){
+        self.value = (self.value & 0b1111_1110) | (val as u8);
+    }
+
+    pub fn get_Z(&self) -> bool{
+        self.value & 0b0000_0010 != 0
+    }
+
+    pub fn get_N(&self) -> bool{
+        self.value & 0b1000_0000 != 0
+    }
+
+    pub fn get_C(&self) -> bool{
+        self.value & 0b0000_0001 != 0
+    }
+
+    pub fn get_D(&self) -> bool{
+        self.value & 0b0000_1000 != 0
+    }
+
+    pub fn set_D(&mut self, val: bool){
+        self.value = (self.value & 0b1111_0111) | ((val as u8) << 3);
+    }
+
+    pub fn get_V(&self) -> bool{
+        self.value & 0b0100_0000 != 0
+    }
+
+    pub fn set_V(&mut self, val: bool){
+        self.value = (self.value & 0b1011_1111) | ((val as u8) << 6);
+    }
+
+    pub fn get_I(&self) -> bool{
+        self.value & 0b0000_0100 != 0
+    }
+
+    pub fn set_I(&mut self, val: bool){
+        self.value = (self.value & 0b1111_1011) | ((val as u8) << 2);
+    }
+
+    /// B only exists on the stack, it tells BRK and PHP apart from IRQ and NMI there.
+    pub fn get_B(&self) -> bool{
+        self.value & 0b0001_0000 != 0
+    }
+}
+
+impl core::fmt::Debug for StatusRegister{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        write!(fmt, "NV-BDIZC {:08b}", self.value)
+    }
+}
+
+/// Which silicon `CPU6502` emulates.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum CpuVariant{
+    /// NMOS 6502/6510 including the undocumented opcodes.
+    Nmos6502,
+    /// WDC 65C02 with the Rockwell bit instructions, WAI and STP.
+    Cmos65C02,
+}
+
+/// Magic constants of the unstable ANE (0x8b) and LXA (0xab) opcodes.
+/// They differ between chips and even with temperature, 0xee matches most 6510s.
+#[derive(Clone,Copy,Debug)]
+pub struct UnstableConstants{
+    pub ane: u8,
+    pub lxa: u8,
+}
+
+impl Default for UnstableConstants{
+    fn default() -> Self{
+        UnstableConstants { ane: 0xee, lxa: 0xee }
+    }
+}
+
+/// How an instruction uses its effective address, indexed modes spend different dummy cycles for each.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Access{
+    Read,
+    Write,
+    Modify,
+}
+
+/// What a bus cycle was for.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum CycleKind{
+    /// Opcode or operand byte.
+    Fetch,
+    /// Data, pointer, stack or vector access the instruction needs.
+    #[default]
+    Data,
+    /// Spurious access while the CPU is busy otherwise, e.g. the read of the unindexed address or the write back of an unmodified value.
+    Dummy,
+}
+
+/// One access of an instruction or interrupt sequence, RDY stalls are not in the list.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct BusCycle{
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+    pub kind: CycleKind,
+    /// What answered at the address when the access happened.
+    pub bank: Bank,
+}
+
+/// Most bus cycles `CPUState` keeps, enough for every instruction.
+const MAX_BUS_CYCLES: usize = 8;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum AdressingType{
+    Implied,
+    Accumulator,
+    Immediate,
+    Relative,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    ZeroPageIndirect,
+    AbsoluteIndirectX,
+    ZeroPageRelative,
+}
+
+impl AdressingType{
+    /// Instruction length in bytes including the opcode.
+    fn length(self) -> u8{
+        match self {
+            AdressingType::Implied | AdressingType::Accumulator => 1,
+            AdressingType::Absolute | AdressingType::AbsoluteX | AdressingType::AbsoluteY | AdressingType::Indirect |
+            AdressingType::AbsoluteIndirectX | AdressingType::ZeroPageRelative => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// What `run_single` does when the program traps itself.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum HaltPolicy{
+    /// Keep running like the hardware does, `JMP *` is a valid idle loop while interrupts do the work.
+    #[default]
+    Run,
+    /// Report an instruction that jumps or branches to itself.
+    HaltOnSelfLoop,
+}
+
+/// Why the CPU stopped under its `HaltPolicy`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum HaltReason{
+    SelfLoop,
+}
+
+/// Why `run_single` stopped instead of completing an instruction.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum CpuError{
+    /// An undocumented opcode was fetched while they are trapped, nothing was executed.
+    IllegalOpcode{ pc: u16, opcode: u8 },
+    /// A JAM (or 65C02 STP) opcode stopped the CPU, only a reset recovers from it.
+    Jam{ pc: u16, opcode: u8 },
+    /// The `HaltPolicy` stopped the CPU.
+    Halted{ pc: u16, reason: HaltReason },
+    /// Execution reached a breakpoint.
+    Breakpoint{ pc: u16 },
+    /// The instruction at `pc` accessed a watched address.
+    Watchpoint{ pc: u16, address: u16, value: u8, write: bool },
+    /// The instruction at `pc` accessed an address with nothing behind it.
+    BusFault{ pc: u16, address: u16 },
+}
+
+impl CpuError {
+    /// Address of the instruction that caused the error.
+    pub fn pc(&self) -> u16{
+        match *self {
+            CpuError::IllegalOpcode { pc, .. } | CpuError::Jam { pc, .. } | CpuError::Halted { pc, .. } |
+            CpuError::Breakpoint { pc } | CpuError::Watchpoint { pc, .. } | CpuError::BusFault { pc, .. } => pc,
+        }
+    }
+}
+
+impl core::fmt::Display for CpuError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        match *self {
+            CpuError::IllegalOpcode { pc, opcode } => write!(fmt, "[Illegal opcode {:#04x} PC={:#06x}]", opcode, pc),
+            CpuError::Jam { pc, opcode } => write!(fmt, "[JAM opcode {:#04x} PC={:#06x}]", opcode, pc),
+            CpuError::Halted { pc, reason } => write!(fmt, "[Halted: {:?} PC={:#06x}]", reason, pc),
+            CpuError::Breakpoint { pc } => write!(fmt, "[Breakpoint PC={:#06x}]", pc),
+            CpuError::Watchpoint { pc, address, value, write } => {
+                let access = if write { "write" } else { "read" };
+                write!(fmt, "[Watchpoint {} ADDR={:#06x} VAL={:#04x} PC={:#06x}]", access, address, value, pc)
+            }
+            CpuError::BusFault { pc, address } => write!(fmt, "[Bus fault ADDR={:#06x} PC={:#06x}]", address, pc),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CpuError {
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum InterruptType {
+    INT,
+    NMI,
+    BRK,
+}
+
+/// Values of the programmer visible registers.
+#[allow(non_snake_case)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct CpuRegisters{
+    pub A: u8,
+    pub X: u8,
+    pub Y: u8,
+    pub SP: u8,
+    pub PC: u16,
+    pub P: StatusRegister,
+}
+
+#[derive(Clone,Copy)]
+#[allow(non_snake_case)]
+pub struct CPUState{
+    ins: u8,
+    bytes: [u8; 3],
+    op1: u8,
+    op2: u8,
+
+    A:   u8,
+    X:   u8,
+    Y:   u8,
+    SP:  u8,
+    P:   StatusRegister,
+    PC:  u16,
+    adr: u16,
+    cycles: u64,
+    variant: CpuVariant,
+    before: CpuRegisters,
+    interrupt: Option<InterruptType>,
+    elapsed: u32,
+    access: Option<Access>,
+    bus: [BusCycle; MAX_BUS_CYCLES],
+    bus_len: u8,
+}
+
+impl CPUState {
+    /// The instruction this state was captured for.
+    pub fn disassemble(&self) -> Instruction{
+        Instruction::decode(self.PC, self.bytes, self.variant)
+    }
+
+    /// Registers after the instruction ran, PC is where the instruction starts.
+    pub fn registers(&self) -> CpuRegisters{
+        CpuRegisters { A: self.A, X: self.X, Y: self.Y, SP: self.SP, PC: self.PC, P: self.P }
+    }
+
+    /// Registers when the instruction was fetched, as VICE and nestest logs show them.
+    pub fn registers_before(&self) -> CpuRegisters{
+        self.before
+    }
+
+    /// IRQ or NMI for a hardware interrupt sequence, None for instructions including BRK.
+    pub fn interrupt(&self) -> Option<InterruptType>{
+        self.interrupt
+    }
+
+    /// Opcode byte, 0x00 for a hardware interrupt sequence as well as BRK.
+    pub fn opcode(&self) -> u8{
+        self.ins
+    }
+
+    /// Opcode and operand bytes, only the first `disassemble().length` are used.
+    pub fn bytes(&self) -> [u8; 3]{
+        self.bytes
+    }
+
+    /// The value read or written, and the result of a read-modify-write.
+    pub fn operands(&self) -> (u8, u8){
+        (self.op1, self.op2)
+    }
+
+    /// Effective address, branch target or vector the instruction used.
+    pub fn address(&self) -> u16{
+        self.adr
+    }
+
+    /// Cycle counter when the instruction started.
+    pub fn cycles(&self) -> u64{
+        self.cycles
+    }
+
+    /// How a load, store or read-modify-write used `address`, None for other instructions.
+    pub fn access(&self) -> Option<Access>{
+        self.access
+    }
+
+    /// Every read and write in order, dummy cycles marked as such.
+    pub fn bus_cycles(&self) -> &[BusCycle]{
+        &self.bus[..self.bus_len as usize]
+    }
+
+    /// Cycles the instruction took, RDY stalls included.
+    pub fn elapsed_cycles(&self) -> u32{
+        self.elapsed
+    }
+
+    pub fn variant(&self) -> CpuVariant{
+        self.variant
+    }
+
+    fn new(cpu: &CPU6502, ins: u8) -> Self{
+        CPUState { ins, bytes: [ins, 0, 0], op1: 0, op2: 0, A: cpu.A, X: cpu.X, Y: cpu.Y, P: cpu.P, SP: cpu.SP, PC: cpu.PC, adr: 0, cycles: cpu.cycles, variant: cpu.variant,
+            before: cpu.registers(), interrupt: None, elapsed: 0, access: None, bus: [BusCycle::default(); MAX_BUS_CYCLES], bus_len: 0 }
+    }
+}
+
+impl core::fmt::Debug for CPUState{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        write!(fmt, "INS={:#04x} A={:#04x} X={:#04x} Y={:#04x} P={:?} SP={:#04x} PC={:#06x} OP1={:#04x} OP2={:#04x} ADDR={:#06x} CYC={} {}",
+        self.ins, self.A, self.X, self.Y, self.P, self.SP, self.PC, self.op1, self.op2, self.adr, self.cycles, self.disassemble())
+    }
+}
+
+#[allow(non_snake_case)]
+pub struct CPU6502{
+    A:  u8,
+    X:  u8,
+    Y:  u8,
+    PC: u16,
+    SP:  u8,
+    P:  StatusRegister,
+
+    fetched: [u8; 3],
+    fetched_len: usize,
+
+    cycles: u64,
+    last_cycles: u8,
+    extra_cycles: u8,
+    page_crossed: bool,
+
+    variant: CpuVariant,
+    halt_policy: HaltPolicy,
+    jammed: bool,
+    waiting: bool,
+    trap_undocumented: bool,
+    unstable: UnstableConstants,
+
+    bus_cycle: u8,
+    irq_samples: u16,
+    nmi_level: bool,
+    nmi_edge: Option<u8>,
+    pending_interrupt: Option<InterruptType>,
+    so_level: bool,
+    stall_cycles: u16,
+
+    trace: Option<TraceBuffer>,
+    trace_hook: Option<fn(&CPUState)>,
+    traced: Option<CPUState>,
+    cycle_kind: CycleKind,
+    bus: [BusCycle; MAX_BUS_CYCLES],
+    bus_len: u8,
+
+    #[cfg(feature = "std")]
+    breakpoints: breakpoints::Breakpoints,
+    #[cfg(feature = "std")]
+    call_stack: callstack::CallStack,
+}
+
+/// Most instructions the built in trace can keep without the `std` feature.
+pub const TRACE_CAPACITY: usize = 64;
+
+/// Ring of the last instructions.
+#[cfg(feature = "std")]
+struct TraceBuffer{
+    entries: std::collections::VecDeque<CPUState>,
+    limit: usize,
+}
+
+#[cfg(feature = "std")]
+impl TraceBuffer{
+    fn new(limit: usize) -> Self{
+        TraceBuffer { entries: std::collections::VecDeque::new(), limit: limit.max(1) }
+    }
+
+    fn push(&mut self, state: CPUState){
+        if self.entries.len() == self.limit{
+            self.entries.pop_front();
+        }
+        self.entries.push_back(state);
+    }
+
+    /// Oldest first.
+    fn iter(&self) -> impl Iterator<Item = &CPUState>{
+        self.entries.iter()
+    }
+
+    fn last(&self) -> Option<&CPUState>{
+        self.entries.back()
+    }
+}
+
+/// Ring of the last instructions, fixed size so tracing never allocates.
+#[cfg(not(feature = "std"))]
+struct TraceBuffer{
+    entries: [Option<CPUState>; TRACE_CAPACITY],
+    start: usize,
+    len: usize,
+    limit: usize,
+}
+
+#[cfg(not(feature = "std"))]
+impl TraceBuffer{
+    fn new(limit: usize) -> Self{
+        TraceBuffer { entries: [None; TRACE_CAPACITY], start: 0, len: 0, limit: limit.clamp(1, TRACE_CAPACITY) }
+    }
+
+    fn push(&mut self, state: CPUState){
+        if self.len < self.limit{
+            self.entries[self.len] = Some(state);
+            self.len += 1;
+        }
+        else{
+            self.entries[self.start] = Some(state);
+            self.start = (self.start + 1) % self.limit;
+        }
+    }
+
+    /// Oldest first.
+    fn iter(&self) -> impl Iterator<Item = &CPUState>{
+        (0..self.len).filter_map(|i| self.entries[(self.start + i) % self.limit].as_ref())
+    }
+
+    fn last(&self) -> Option<&CPUState>{
+        self.iter().last()
+    }
+}
+
+impl core::fmt::Debug for CPU6502 {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        write!(fmt, "PC={:#06x} A={:#04x} X={:#04x} Y={:#04x} SP={:#04x} P={:?} CYC={}"
+                                        , self.PC, self.A, self.X, self.Y, self.SP, self.P, self.cycles)
+    }
+}
+
+impl CPU6502{
+    pub fn new() -> Self{
+        CPU6502::new_variant(CpuVariant::Nmos6502)
+    }
+
+    pub fn new_variant(variant: CpuVariant) -> Self{
+        CPU6502 { A: 0, X: 0, Y: 0, PC: 0, SP: 0xff, P: StatusRegister { value: 0x34 }, fetched: [0; 3], fetched_len: 0,
+            cycles: 0, last_cycles: 0, extra_cycles: 0, page_crossed: false,
+            variant, halt_policy: HaltPolicy::default(), jammed: false, waiting: false, trap_undocumented: false, unstable: UnstableConstants::default(),
+            bus_cycle: 0, irq_samples: 0, nmi_level: false, nmi_edge: None, pending_interrupt: None, so_level: false, stall_cycles: 0, trace: None, trace_hook: None, traced: None,
+            cycle_kind: CycleKind::Data, bus: [BusCycle::default(); MAX_BUS_CYCLES], bus_len: 0,
+            #[cfg(feature = "std")]
+            breakpoints: breakpoints::Breakpoints::default(),
+            #[cfg(feature = "std")]
+            call_stack: callstack::CallStack::new() }
+    }
+
+    /// Total number of cycles executed since the CPU was created.
+    pub fn cycles(&self) -> u64{
+        self.cycles
+    }
+
+    /// Number of cycles taken by the last instruction or interrupt sequence.
+    pub fn last_instruction_cycles(&self) -> u8{
+        self.last_cycles
+    }
+
+    /// Number of cycles the last instruction or interrupt sequence spent waiting for RDY, on top of its own.
+    pub fn last_stall_cycles(&self) -> u16{
+        self.stall_cycles
+    }
+
+    pub fn variant(&self) -> CpuVariant{
+        self.variant
+    }
+
+    /// True after a JAM (or 65C02 STP) opcode stopped the CPU, only a reset recovers from it.
+    pub fn is_jammed(&self) -> bool{
+        self.jammed
+    }
+
+    pub fn set_halt_policy(&mut self, policy: HaltPolicy){
+        self.halt_policy = policy;
+    }
+
+    /// When set, undocumented NMOS opcodes are reported as `CpuError::IllegalOpcode` instead of being executed.
+    pub fn set_trap_undocumented(&mut self, trap: bool){
+        self.trap_undocumented = trap;
+    }
+
+    pub fn set_unstable_constants(&mut self, constants: UnstableConstants){
+        self.unstable = constants;
+    }
+
+    /// Keeps the last `trace_size_limit` instructions, at most `TRACE_CAPACITY` without the `std` feature.
+    pub fn enable_trace(&mut self, trace_size_limit: usize){
+        if self.trace.is_none(){
+            self.trace = Some(TraceBuffer::new(trace_size_limit));
+        }
+    }
+
+    /// Called with every executed instruction and interrupt sequence, whether or not the trace is enabled.
+    pub fn set_trace_hook(&mut self, hook: Option<fn(&CPUState)>){
+        self.trace_hook = hook;
+    }
+
+    /// Breakpoints and watchpoints, `run_single` returns `CpuError::Breakpoint` before an instruction it stops at
+    /// and `CpuError::Watchpoint` after one. Running on goes past the breakpoint.
+    #[cfg(feature = "std")]
+    pub fn breakpoints(&self) -> &breakpoints::Breakpoints{
+        &self.breakpoints
+    }
+
+    #[cfg(feature = "std")]
+    pub fn breakpoints_mut(&mut self) -> &mut breakpoints::Breakpoints{
+        &mut self.breakpoints
+    }
+
+    /// JSR, BRK and interrupt frames of everything run since the last reset, for backtraces.
+    #[cfg(feature = "std")]
+    pub fn call_stack(&self) -> &callstack::CallStack{
+        &self.call_stack
+    }
+
+    pub fn reset<MemT: Memory6502>(&mut self, memory: &mut MemT) {
+        let resetvec_addr = memory.read_memory_word(0xfffc);
+        self.PC = resetvec_addr;
+        self.P.value = 0x34; // Ensure interrupts are disabled on reset
+        self.jammed = false;
+        self.waiting = false;
+        self.nmi_edge = None;
+        self.pending_interrupt = None;
+        #[cfg(feature = "std")]
+        self.call_stack.clear();
+    }
+
+    pub fn reset_at(&mut self, start_address: u16) {
+        self.PC = start_address;
+        #[cfg(feature = "std")]
+        self.call_stack.clear();
+    }
+
+    fn adc(&mut self, value: u8){
+        if self.P.get_D(){
+            self.adc_decimal(value);
+            return;
+        }
+
+        let data = value as u16 + self.A as u16 + self.P.get_C() as u16;
+
+        let a = value & 0x80 != 0;
+        let b = self.A & 0x80 != 0;
+        let c = data as u8 & 0x80 != 0;
+
+        self.P.set_C(data > 0xff);
+        self.P.set_NZ(data as u8);
+        self.P.set_V(!a & !b & c | a & b & !c);
+        self.A = data as u8;
+    }
+
+    fn sbc(&mut self, value: u8){
+        if self.P.get_D(){
+            self.sbc_decimal(value);
+            return;
+        }
+
+        self.adc(!value);
+    }
+
+    /// Decimal mode ADC as the silicon computes it, including invalid BCD digits.
+    /// NMOS takes Z from the binary sum and N and V from the sum before the high digit
+    /// is adjusted, the 65C02 takes N and Z from the final result.
+    fn adc_decimal(&mut self, value: u8){
+        let a = self.A as i16;
+        let b = value as i16;
+        let carry = self.P.get_C() as i16;
+
+        let mut low = (a & 0x0f) + (b & 0x0f) + carry;
+        if low >= 0x0a{
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut data = (a & 0xf0) + (b & 0xf0) + low;
+        let signed = (self.A & 0xf0) as i8 as i16 + (value & 0xf0) as i8 as i16 + low;
+        let negative = data & 0x80 != 0;
+        if data >= 0xa0{
+            data += 0x60;
+        }
+
+        self.A = data as u8;
+        self.P.set_C(data >= 0x100);
+        self.P.set_V(!(-128..=127).contains(&signed));
+        if self.variant == CpuVariant::Cmos65C02{
+            self.P.set_NZ(self.A);
+            self.extra_cycles += 1;
+        }
+        else{
+            self.P.set_N(negative);
+            self.P.set_Z((a + b + carry) & 0xff == 0);
+        }
+    }
+
+    /// Decimal mode SBC, C and V always come from the binary difference.
+    /// NMOS also takes N and Z from it, the 65C02 corrects both digits afterwards and sets N and Z from the result.
+    fn sbc_decimal(&mut self, value: u8){
+        let a = self.A as i16;
+        let b = value as i16;
+        let borrow = !self.P.get_C() as i16;
+
+        let binary = a - b - borrow;
+        let mut low = (a & 0x0f) - (b & 0x0f) - borrow;
+        let data = if self.variant == CpuVariant::Cmos65C02{
+            let mut data = binary;
+            if data < 0{
+                data -= 0x60;
+            }
+            if low < 0{
+                data -= 0x06;
+            }
+            data
+        }
+        else{
+            if low < 0{
+                low = ((low - 0x06) & 0x0f) - 0x10;
+            }
+            let mut data = (a & 0xf0) - (b & 0xf0) + low;
+            if data < 0{
+                data -= 0x60;
+            }
+            data
+        };
+
+        self.A = data as u8;
+        self.P.set_C(binary >= 0);
+        self.P.set_V((a ^ b) & (a ^ binary) & 0x80 != 0);
+        if self.variant == CpuVariant::Cmos65C02{
+            self.P.set_NZ(self.A);
+            self.extra_cycles += 1;
+        }
+        else{
+            self.P.set_NZ(binary as u8);
+        }
+    }
+
+    fn asl(&mut self, value: u8) -> u8{
+        self.P.set_C(value & 0b1000_0000 != 0);
+        let data = value << 1;
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn rol(&mut self, value: u8) -> u8{
+        let data = value << 1 | (self.P.get_C() as u8);
+        self.P.set_C(value & 0b1000_0000 != 0);
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn lsr(&mut self, value: u8) -> u8{
+        self.P.set_C(value & 0b0000_0001 != 0);
+        let data = value >> 1;
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn ror(&mut self, value: u8) -> u8{
+        let data = value >> 1 | ((self.P.get_C() as u8) << 7);
+        self.P.set_C(value & 0b0000_0001 != 0);
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn compare(&mut self, register: u8, value: u8){
+        let r = register.overflowing_sub(value);
+        self.P.set_NZ(r.0);
+        self.P.set_C(!r.1);
+    }
+
+    fn arr(&mut self, value: u8){
+        let and = self.A & value;
+        let carry = self.P.get_C();
+        let mut data = and >> 1 | ((carry as u8) << 7);
+
+        if self.P.get_D(){
+            self.P.set_N(carry);
+            self.P.set_Z(data == 0);
+            self.P.set_V((and ^ data) & 0b0100_0000 != 0);
+            if (and & 0x0f) + (and & 0x01) > 0x05{
+                data = (data & 0xf0) | (data.overflowing_add(0x06).0 & 0x0f);
+            }
+            let high_fix = (and & 0xf0) as u16 + (and & 0x10) as u16 > 0x50;
+            if high_fix{
+                data = data.overflowing_add(0x60).0;
+            }
+            self.P.set_C(high_fix);
+        }
+        else{
+            self.P.set_NZ(data);
+            self.P.set_C(data & 0b0100_0000 != 0);
+            self.P.set_V(((data >> 6) ^ (data >> 5)) & 0x01 != 0);
+        }
+        self.A = data;
+    }
+
+    /// Store of the unstable SHA/SHX/SHY/TAS group. The value is ANDed with the high byte of the
+    /// base address plus one, and on a page crossing that value also replaces the target high byte.
+    fn store_high_and<MemT: Memory6502 + ?Sized>(&mut self, address: u16, value: u8, memory: &mut MemT) -> u16{
+        let high = (address >> 8) as u8;
+        let high = if self.page_crossed { high } else { high.overflowing_add(1).0 };
+        let data = value & high;
+        let address = if self.page_crossed { (data as u16) << 8 | (address & 0x00ff) } else { address };
+        self.bus_write(memory, address, data);
+        address
+    }
+
+    fn branch<MemT: Memory6502 + ?Sized>(&mut self, condition: bool, state: &mut CPUState, memory: &mut MemT){
+        let data = self.fetch(memory) as i8;
+
+        if condition{
+            let r = (self.PC as i16).overflowing_add(data as i16);
+            let target = r.0 as u16;
+            self.dummy_read(memory, self.PC); //read while the offset is added
+            self.extra_cycles += 1;
+            if target & 0xff00 != self.PC & 0xff00{
+                self.dummy_read(memory, (self.PC & 0xff00) | (target & 0x00ff)); //read while PCH is fixed
+                self.extra_cycles += 1;
+            }
+            self.PC = target;
+            state.adr = self.PC;
+        }
+
+        state.op1 = data as u8;
+    }
+
+    fn add_trace(&mut self, state: CPUState){
+        if let Some(buf) = self.trace.as_mut(){
+            buf.push(state);
+        }
+        if let Some(hook) = self.trace_hook{
+            hook(&state);
+        }
+        #[cfg(feature = "std")]
+        self.call_stack.update(&state);
+        self.traced = Some(state);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn show_trace(&self){
+        if let Some(buf) = self.trace.as_ref(){
+            println!("****  Trace   ****");
+            for i in buf.iter(){
+                println!("{:?}", i);
+            }
+        }
+    }
+
+    ///will panic if trace is not enabled
+    pub fn get_last_state(&self) -> CPUState{
+        *self.trace.as_ref().expect("Trace need to be enabled").last().expect("To trace")
+    }
+
+    #[cfg(feature = "std")]
+    pub fn show_cpu_debug(&self){
+            self.show_trace();
+            println!("{:?}", self);
+    }
+
+    fn bus_read<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, address: u16) -> u8{
+        self.wait_rdy(memory);
+        let bank = memory.bank(address);
+        let data = memory.read_memory(address);
+        self.record_cycle(address, data, false, bank);
+        self.sample_inputs(memory);
+        data
+    }
+
+    fn bus_write<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, address: u16, value: u8){
+        if self.variant == CpuVariant::Cmos65C02{
+            self.wait_rdy(memory); //the NMOS part ignores RDY on writes
+        }
+        let bank = memory.bank(address);
+        memory.write_memory(address, value);
+        self.record_cycle(address, value, true, bank);
+        self.sample_inputs(memory);
+    }
+
+    /// Keeps the access for the trace, watchpoints only see the data accesses.
+    fn record_cycle(&mut self, address: u16, value: u8, write: bool, bank: Bank){
+        if (self.bus_len as usize) < MAX_BUS_CYCLES{
+            self.bus[self.bus_len as usize] = BusCycle { address, value, write, kind: self.cycle_kind, bank };
+            self.bus_len += 1;
+        }
+        #[cfg(feature = "std")]
+        if self.cycle_kind == CycleKind::Data && !self.breakpoints.is_empty(){
+            self.breakpoints.access(address, value, write);
+        }
+    }
+
+    /// A spurious read, the value is thrown away.
+    fn dummy_read<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, address: u16){
+        self.cycle_kind = CycleKind::Dummy;
+        self.bus_read(memory, address);
+        self.cycle_kind = CycleKind::Data;
+    }
+
+    fn dummy_write<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, address: u16, value: u8){
+        self.cycle_kind = CycleKind::Dummy;
+        self.bus_write(memory, address, value);
+        self.cycle_kind = CycleKind::Data;
+    }
+
+    /// Holds the bus while RDY is low, every stalled cycle counts.
+    fn wait_rdy<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT){
+        while !memory.rdy(){
+            self.stall_cycles = self.stall_cycles.saturating_add(1);
+            self.cycles += 1;
+        }
+    }
+
+    /// Starts counting bus cycles for a new instruction, an NMI edge seen earlier stays latched.
+    fn begin_cycles(&mut self){
+        self.bus_cycle = 0;
+        self.bus_len = 0;
+        self.stall_cycles = 0;
+        self.irq_samples = 0;
+        if self.nmi_edge.is_some(){
+            self.nmi_edge = Some(0);
+        }
+    }
+
+    /// Samples IRQ, NMI and SO at the end of a bus cycle, NMI is latched and SO sets V on its falling edge.
+    fn sample_inputs<MemT: Memory6502 + ?Sized>(&mut self, memory: &MemT){
+        let so = memory.so();
+        if so && !self.so_level{
+            self.P.set_V(true);
+        }
+        self.so_level = so;
+
+        let (irq, nmi) = memory.interrupt_controller().map_or((false, false), |lines| (lines.irq(), lines.nmi()));
+        if irq{
+            self.irq_samples |= 1 << self.bus_cycle;
+        }
+        if nmi && !self.nmi_level && self.nmi_edge.is_none(){
+            self.nmi_edge = Some(self.bus_cycle);
+        }
+        self.nmi_level = nmi;
+        if self.bus_cycle < 15{
+            self.bus_cycle += 1;
+        }
+    }
+
+    /// Decides whether an interrupt sequence follows, from the lines as they were in cycle `poll` of the instruction.
+    fn poll_interrupts(&mut self, poll: u8, irq_masked: bool){
+        let poll = poll.min(self.bus_cycle.saturating_sub(1));
+        if self.nmi_edge.is_some_and(|cycle| cycle <= poll){
+            self.pending_interrupt = Some(InterruptType::NMI);
+        }
+        else if !irq_masked && self.irq_samples & (1 << poll) != 0{
+            self.pending_interrupt = Some(InterruptType::INT);
+        }
+    }
+
+    /// Reads the next instruction byte, the first ones are kept for the trace.
+    fn fetch<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT) -> u8{
+        self.cycle_kind = CycleKind::Fetch;
+        let data = self.bus_read(memory, self.PC);
+        self.cycle_kind = CycleKind::Data;
+        self.PC = self.PC.overflowing_add(1).0;
+        if self.fetched_len < self.fetched.len(){
+            self.fetched[self.fetched_len] = data;
+            self.fetched_len += 1;
+        }
+        data
+    }
+
+    fn fetch_word<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT) -> u16{
+        let lo = self.fetch(memory);
+        let hi = self.fetch(memory);
+        (hi as u16) << 8 | lo as u16
+    }
+
+    /// Writes to the stack and returns the address written.
+    fn push<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, value: u8) -> u16{
+        let address = 0x0100 | self.SP as u16;
+        self.bus_write(memory, address, value);
+        self.SP = self.SP.overflowing_sub(1).0;
+        address
+    }
+
+    fn pull<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT) -> u8{
+        self.SP = self.SP.overflowing_add(1).0;
+        self.bus_read(memory, 0x0100 | self.SP as u16)
+    }
+
+    /// Read-modify-write instructions spend a cycle between reading and writing the value.
+    /// NMOS writes the unmodified value back during it, the 65C02 reads the address again.
+    fn rmw_dummy<MemT: Memory6502 + ?Sized>(&mut self, memory: &mut MemT, address: u16, data: u8){
+        if self.variant == CpuVariant::Cmos65C02{
+            self.dummy_read(memory, address);
+        }
+        else{
+            self.dummy_write(memory, address, data);
+        }
+    }
+
+    /// Adds the index to the base address. While the high byte is carried NMOS reads the
+    /// address with the old high byte, the 65C02 reads the last operand byte again. Reads
+    /// only spend that cycle on a page crossing, writes and read-modify-writes always do.
+    fn indexed<MemT: Memory6502 + ?Sized>(&mut self, base: u16, index: u8, access: Access, memory: &mut MemT) -> u16{
+        let address = base.overflowing_add(index as u16).0;
+        self.page_crossed = base & 0xff00 != address & 0xff00;
+        if self.page_crossed || access != Access::Read{
+            let dummy = if self.variant == CpuVariant::Cmos65C02{
+                self.PC.overflowing_sub(1).0
+            }
+            else{
+                (base & 0xff00) | (address & 0x00ff)
+            };
+            self.dummy_read(memory, dummy);
+        }
+        address
+    }
+
+    fn get_address<MemT: Memory6502 + ?Sized>(&mut self, adrtype: AdressingType, access: Access, memory: &mut MemT) -> u16{
+        match adrtype {
+            AdressingType::ZeroPage => {
+                self.fetch(memory) as u16
+            }
+            AdressingType::ZeroPageX => {
+                let addr = self.fetch(memory);
+                self.dummy_read(memory, addr as u16); //read while X is added
+                addr.overflowing_add(self.X).0 as u16
+            }
+            AdressingType::ZeroPageY => {
+                let addr = self.fetch(memory);
+                self.dummy_read(memory, addr as u16); //read while Y is added
+                addr.overflowing_add(self.Y).0 as u16
+            }
+            AdressingType::Absolute => {
+                self.fetch_word(memory)
+            }
+            AdressingType::AbsoluteX => {
+                let base = self.fetch_word(memory);
+                self.indexed(base, self.X, access, memory)
+            }
+            AdressingType::AbsoluteY => {
+                let base = self.fetch_word(memory);
+                self.indexed(base, self.Y, access, memory)
+            }
+            AdressingType::Indirect => {
+                let addr1 = self.fetch_word(memory);
+                let lo = self.bus_read(memory, addr1);
+                let addr2 = if self.variant == CpuVariant::Cmos65C02{
+                    self.dummy_read(memory, addr1); //65C02 spends a cycle carrying into the high byte
+                    addr1.overflowing_add(1).0
+                }
+                else{
+                    (addr1 & 0xff00) | (addr1.overflowing_add(1).0 & 0x00ff) //NMOS JMP ($xxFF) does not carry
+                };
+                let hi = self.bus_read(memory, addr2);
+                (hi as u16) << 8 | lo as u16
+            }
+            AdressingType::IndirectX => {
+                let addr1 = self.fetch(memory);
+                self.dummy_read(memory, addr1 as u16); //read while X is added
+                let addr1 = addr1.overflowing_add(self.X).0;
+                let lo = self.bus_read(memory, addr1 as u16);
+                let hi = self.bus_read(memory, addr1.overflowing_add(1).0 as u16); //pointer wraps in zero page
+                (hi as u16) << 8 | lo as u16
+            }
+            AdressingType::ZeroPageIndirect => {
+                let addr1 = self.fetch(memory);
+                let lo = self.bus_read(memory, addr1 as u16);
+                let hi = self.bus_read(memory, addr1.overflowing_add(1).0 as u16);
+                (hi as u16) << 8 | lo as u16
+            }
+            AdressingType::AbsoluteIndirectX => {
+                let addr1 = self.fetch_word(memory);
+                self.dummy_read(memory, self.PC.overflowing_sub(1).0); //read while X is added
+                let addr1 = addr1.overflowing_add(self.X as u16).0;
+                let lo = self.bus_read(memory, addr1);
+                let hi = self.bus_read(memory, addr1.overflowing_add(1).0);
+                (hi as u16) << 8 | lo as u16
+            }
+            AdressingType::IndirectY => {
+                let addr1 = self.fetch(memory);
+                let lo = self.bus_read(memory, addr1 as u16);
+                let hi = self.bus_read(memory, addr1.overflowing_add(1).0 as u16); //pointer wraps in zero page
+                let base = (hi as u16) << 8 | lo as u16;
+                self.indexed(base, self.Y, access, memory)
+            }
+            AdressingType::Implied | AdressingType::Accumulator | AdressingType::Immediate | AdressingType::Relative | AdressingType::ZeroPageRelative => {
+                unreachable!("{:?} has no effective address", adrtype)
+            }
+        }
+    }
+
+    pub fn run_single<MemT: Memory6502>(&mut self, memory: &mut MemT) -> Result<u16, CpuError>{
+        if self.jammed || self.waiting{
+            // A stopped CPU keeps its clock running but does not fetch until reset or interrupt
+            self.last_cycles = 1;
+            self.cycles += 1;
+            if self.jammed{
+                return Err(self.jam_error());
+            }
+            self.begin_cycles();
+            self.sample_inputs(memory);
+            if self.nmi_edge.is_some() || self.irq_samples != 0{
+                // WAI wakes up on IRQ even when it is masked, then just carries on
+                self.waiting = false;
+                self.poll_interrupts(0, self.P.get_I());
+            }
+            return Ok(self.PC);
+        }
+
+        self.begin_cycles();
+        if let Some(int) = self.pending_interrupt.take(){
+            let pc = self.PC;
+            self.interrupt_sequence(int, memory);
+            return self.check_watchpoints(pc, memory).map(|_| pc);
+        }
+
+        #[cfg(feature = "std")]
+        if !self.breakpoints.is_empty() && self.breakpoints.check_execute(&self.registers(), memory){
+            self.last_cycles = 0;
+            return Err(CpuError::Breakpoint { pc: self.PC });
+        }
+
+        let irq_masked = self.P.get_I();
+        //build cpu state before the fetch so an RDY stall on it counts for this instruction
+        let mut cpu_state = CPUState::new(self, 0x00);
+        self.cycle_kind = CycleKind::Fetch;
+        let ins = self.bus_read(memory, self.PC);
+        self.cycle_kind = CycleKind::Data;
+        cpu_state.ins = ins;
+        let return_pc = self.PC;
+        self.PC = self.PC.overflowing_add(1).0; //PC wraps from $ffff to $0000 like on the hardware
+        self.fetched = [ins, 0, 0];
+        self.fetched_len = 1;
+        self.extra_cycles = 0;
+        self.page_crossed = false;
+
+        let opcode = &opcodes::opcode_table(self.variant)[ins as usize];
+        if opcode.undocumented && self.trap_undocumented{
+            self.PC = return_pc;
+            self.last_cycles = 0;
+            return Err(CpuError::IllegalOpcode { pc: return_pc, opcode: ins });
+        }
+        if opcode.cycles > 1 && matches!(opcode.mode, AdressingType::Implied | AdressingType::Accumulator){
+            // Single byte instructions still read the byte after the opcode and discard it
+            self.dummy_read(memory, self.PC);
+        }
+
+        match opcode.operation {
+            Operation::Read(operation) => {
+                let (address, data) = if opcode.mode == AdressingType::Immediate{
+                    (self.PC, self.fetch(memory))
+                }
+                else{
+                    let address = self.get_address(opcode.mode, Access::Read, memory);
+                    (address, self.bus_read(memory, address))
+                };
+                let extra_cycles = self.extra_cycles;
+                operation(self, data);
+                if self.extra_cycles != extra_cycles{
+                    // The 65C02 spends the decimal fix up cycle reading the operand again
+                    self.dummy_read(memory, address);
+                }
+
+                cpu_state.op1 = data;
+                cpu_state.adr = address;
+                if opcode.mode != AdressingType::Immediate{
+                    cpu_state.access = Some(Access::Read);
+                }
+            }
+
+            Operation::Write(operation) => {
+                let address = self.get_address(opcode.mode, Access::Write, memory);
+                let data = operation(self);
+                self.bus_write(memory, address, data);
+
+                cpu_state.op1 = data;
+                cpu_state.adr = address;
+                cpu_state.access = Some(Access::Write);
+            }
+
+            Operation::Modify(operation) if opcode.mode == AdressingType::Accumulator => {
+                cpu_state.op1 = self.A;
+                self.A = operation(self, self.A);
+                cpu_state.op2 = self.A;
+            }
+
+            Operation::Modify(operation) => {
+                // The 65C02 shifts with a page penalty only spend the fix up cycle when the page is crossed
+                let access = if opcode.page_penalty { Access::Read } else { Access::Modify };
+                let address = self.get_address(opcode.mode, access, memory);
+                let data = self.bus_read(memory, address);
+                self.rmw_dummy(memory, address, data);
+                let result = operation(self, data);
+                self.bus_write(memory, address, result);
+
+                cpu_state.op1 = data;
+                cpu_state.op2 = result;
+                cpu_state.adr = address;
+                cpu_state.access = Some(Access::Modify);
+            }
+
+            Operation::Register(operation) => {
+                operation(self);
+            }
+
+            Operation::Branch(condition) => {
+                let data = if opcode.mode == AdressingType::ZeroPageRelative{
+                    let address = self.get_address(AdressingType::ZeroPage, Access::Read, memory);
+                    self.bus_read(memory, address)
+                }
+                else{
+                    0
+                };
+                cpu_state.op2 = data;
+                let taken = condition(self, data);
+                self.branch(taken, &mut cpu_state, memory);
+            }
+
+            Operation::Control(operation) => {
+                cpu_state.adr = operation(self, opcode.mode, memory);
+            }
+        }
+
+        cpu_state.bytes = self.fetched;
+        cpu_state.A = self.A;
+        cpu_state.X = self.X;
+        cpu_state.Y = self.Y;
+        cpu_state.SP = self.SP;
+        cpu_state.P = self.P;
+
+        let mut cycles = opcode.cycles + self.extra_cycles;
+        if self.page_crossed && opcode.page_penalty{
+            cycles += 1;
+        }
+        self.last_cycles = cycles;
+        self.cycles += cycles as u64;
+        cpu_state.elapsed = (self.cycles - cpu_state.cycles) as u32;
+        cpu_state.bus = self.bus;
+        cpu_state.bus_len = self.bus_len;
+        self.add_trace(cpu_state);
+
+        // Interrupts are polled in the next to last cycle, a taken branch that stays in its page polls before its last two
+        let poll = if opcode.mode == AdressingType::Relative && self.extra_cycles == 1 { opcode.cycles - 2 } else { cycles.saturating_sub(2) };
+        // CLI, SEI and PLP change I in their last cycle, after the poll
+        let irq_masked = if matches!(ins, 0x28 | 0x58 | 0x78) { irq_masked } else { self.P.get_I() };
+        self.poll_interrupts(poll, irq_masked);
+
+        if let Some(address) = memory.take_bus_fault(){
+            return Err(CpuError::BusFault { pc: return_pc, address });
+        }
+        if self.jammed{
+            return Err(self.jam_error());
+        }
+        self.check_watchpoints(return_pc, memory)?;
+        if self.halt_policy == HaltPolicy::HaltOnSelfLoop && self.PC == return_pc{
+            return Err(CpuError::Halted { pc: self.PC, reason: HaltReason::SelfLoop });
+        }
+
+        Ok(return_pc)
+    }
+
+    /// `run_single` handing the instruction or interrupt sequence it ran to `sink`, also when it returns an error.
+    pub fn run_single_traced<MemT: Memory6502, SinkT: TraceSink + ?Sized>(&mut self, memory: &mut MemT, sink: &mut SinkT) -> Result<u16, CpuError>{
+        self.traced = None;
+        let result = self.run_single(memory);
+        if let Some(state) = self.traced.take(){
+            sink.trace(&state, memory);
+        }
+        result
+    }
+
+    /// Runs until `command` is done, an error of any step ends it early, e.g. a breakpoint.
+    pub fn run_command<MemT: Memory6502, SinkT: TraceSink + ?Sized>(&mut self, memory: &mut MemT, command: RunCommand, sink: &mut SinkT) -> Result<u16, CpuError>{
+        let mut until = RunUntil::new(command, &self.registers(), self.cycles);
+        loop{
+            let pc = self.run_single_traced(memory, &mut (&mut until, &mut *sink))?;
+            if until.is_done(&self.registers(), self.cycles){
+                return Ok(pc);
+            }
+        }
+    }
+
+    /// Stops after an instruction or interrupt sequence that accessed a watched address.
+    #[cfg(feature = "std")]
+    fn check_watchpoints<MemT: Memory6502>(&mut self, pc: u16, memory: &mut MemT) -> Result<(), CpuError>{
+        if self.breakpoints.is_empty(){
+            return Ok(());
+        }
+        match self.breakpoints.check_accesses(&self.registers(), memory) {
+            Some((address, value, write)) => Err(CpuError::Watchpoint { pc, address, value, write }),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn check_watchpoints<MemT: Memory6502>(&mut self, _pc: u16, _memory: &mut MemT) -> Result<(), CpuError>{
+        Ok(())
+    }
+
+    /// The jamming opcode was the last one fetched and PC stays right after it.
+    fn jam_error(&self) -> CpuError{
+        CpuError::Jam { pc: self.PC.overflowing_sub(1).0, opcode: self.fetched[0] }
+    }
+
+    /// Runs the interrupt sequence right away unless a maskable interrupt is disabled, bypassing the IRQ and NMI lines.
+    /// Returns the number of cycles taken, 7 when the interrupt was serviced and 0 otherwise.
+    pub fn interrupt<MemT: Memory6502>(&mut self, int: InterruptType, memory: &mut MemT) -> u8{
+        self.waiting = false; // WAI resumes even when the interrupt is masked
+        if self.jammed || (self.P.get_I() && int == InterruptType::INT){
+            return 0;
+        }
+        self.begin_cycles();
+        self.interrupt_sequence(int, memory)
+    }
+
+    fn interrupt_sequence<MemT: Memory6502>(&mut self, int: InterruptType, memory: &mut MemT) -> u8{
+        // Hardware interrupts spend two cycles reading the next opcode and discarding it
+        let mut state = CPUState::new(self, 0x00);
+        self.dummy_read(memory, self.PC);
+        self.dummy_read(memory, self.PC);
+        let (address, taken) = self.push_interrupt(int, memory);
+        state.adr = address;
+        state.interrupt = Some(taken); //traced as NMI when one took over the vector
+        state.SP = self.SP;
+        state.P = self.P;
+
+        let cycles = opcodes::opcode_table(self.variant)[0x00].cycles;
+        self.last_cycles = cycles;
+        self.cycles += cycles as u64;
+        state.elapsed = (self.cycles - state.cycles) as u32;
+        state.bus = self.bus;
+        state.bus_len = self.bus_len;
+        self.add_trace(state);
+        cycles
+    }
+
+    /// Pushes PC and P and jumps through the vector, returns the handler address and the interrupt whose vector was used.
+    fn push_interrupt<MemT: Memory6502 + ?Sized>(&mut self, int: InterruptType, memory: &mut MemT) -> (u16, InterruptType){
+        self.push(memory, (self.PC >> 8) as u8);
+        self.push(memory, (self.PC & 0x0ff) as u8);
+        if int == InterruptType::BRK{
+            self.push(memory, self.P.value | 0b0011_0000); //Set Interrupt flag
+        }
+        else{
+            self.push(memory, (self.P.value & 0b1110_1111) | 0b0010_0000); //B clear for hardware interrupts
+        }
+        // An NMI edge seen before the vector is fetched takes over the vector, B is already pushed
+        let int = if self.nmi_edge.is_some_and(|cycle| cycle <= 3) { InterruptType::NMI } else { int };
+        if int == InterruptType::NMI{
+            self.nmi_edge = None;
+        }
+        let vector = match int {
+            InterruptType::INT | InterruptType::BRK => {
+                0xfffe
+            }
+            InterruptType::NMI => {
+                0xfffa // NMI int vec
+            }
+        };
+        let lo = self.bus_read(memory, vector);
+        let hi = self.bus_read(memory, vector + 1);
+        let address = (hi as u16) << 8 | lo as u16;
+
+        self.PC = address;
+        self.P.set_I(true); //Disable Interupts
+        if self.variant == CpuVariant::Cmos65C02{
+            self.P.set_D(false);
+        }
+        (address, int)
+    }
+}
+
+impl Default for CPU6502{
+    fn default() -> Self{
+        CPU6502::new()
+    }
+}
+
+/// Register access for debuggers, snapshots and test harnesses.
+#[allow(non_snake_case)]
+impl CPU6502{
+    pub fn registers(&self) -> CpuRegisters{
+        CpuRegisters { A: self.A, X: self.X, Y: self.Y, SP: self.SP, PC: self.PC, P: self.P }
+    }
+
+    pub fn set_registers(&mut self, registers: CpuRegisters){
+        self.A = registers.A;
+        self.X = registers.X;
+        self.Y = registers.Y;
+        self.SP = registers.SP;
+        self.PC = registers.PC;
+        self.P = registers.P;
+    }
+
+    pub fn get_A(&self) -> u8{
+        self.A
+    }
+
+    pub fn set_A(&mut self, value: u8){
+        self.A = value;
+    }
+
+    pub fn get_X(&self) -> u8{
+        self.X
+    }
+
+    pub fn set_X(&mut self, value: u8){
+        self.X = value;
+    }
+
+    pub fn get_Y(&self) -> u8{
+        self.Y
+    }
+
+    pub fn set_Y(&mut self, value: u8){
+        self.Y = value;
+    }
+
+    /// Stack pointer, the stack lives at $0100 + SP.
+    pub fn get_SP(&self) -> u8{
+        self.SP
+    }
+
+    pub fn set_SP(&mut self, value: u8){
+        self.SP = value;
+    }
+
+    /// Address of the next instruction to run.
+    pub fn get_PC(&self) -> u16{
+        self.PC
+    }
+
+    pub fn set_PC(&mut self, value: u16){
+        self.PC = value;
+    }
+
+    pub fn get_P(&self) -> StatusRegister{
+        self.P
+    }
+
+    pub fn set_P(&mut self, value: StatusRegister){
+        self.P = value;
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::cpu6502::interrupts::InterruptController;
+    use crate::cpu6502::memory::{Bank,Memory,Memory6502};
+    use crate::cpu6502::coverage::{Coverage,BITMAP_SIZE,OPCODE,OPERAND,READ,WRITE};
+    use crate::cpu6502::{CPU6502,CPUState,CpuError,CycleKind,CpuRegisters,CpuVariant,HaltPolicy,HaltReason,InterruptType,StatusRegister,TRACE_CAPACITY};
+    use crate::cpu6502::assembler::assemble;
+    use crate::cpu6502::breakpoints::{Breakpoint,Condition,WatchKind};
+    use crate::cpu6502::callstack::{Frame, FrameKind};
+    use crate::cpu6502::stepping::RunCommand;
+    use crate::cpu6502::symbols::Symbols;
+    use crate::cpu6502::disassembler::{disassemble,disassemble_range,Instruction};
+    use crate::cpu6502::profiler::{PcProfile,Profiler};
+    use crate::cpu6502::trace::{TraceFilter,TraceLog};
+    use crate::cpu6502::trace_diff::{TraceDiff,TraceRecord};
+    #[test]
+    fn test1(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600,0xa9); //LDA
+        mem.write_memory(0x601,0x10); //#$10
+        mem.write_memory(0x602,0x85); //STA
+        mem.write_memory(0x603,0x00); //$00
+        mem.write_memory(0x604,0x85); //STA
+        mem.write_memory(0x605,0x01); //$01
+        mem.write_memory(0x606,0x06); //ASL
+        mem.write_memory(0x607,0x00); //$00
+        mem.write_memory(0x608,0xa9); //LDA
+        mem.write_memory(0x609,0x30); //#$30
+        mem.write_memory(0x60a,0x85); //STA
+        mem.write_memory(0x60b,0x02); //$02
+        mem.write_memory(0x60c,0xa9); //LDA
+        mem.write_memory(0x60d,0x02); //#$02
+        mem.write_memory(0x60e,0x85); //STA
+        mem.write_memory(0x60f,0x04); //$04
+        mem.write_memory(0x610,0xa9); //LDA
+        mem.write_memory(0x611,0x01); //#$01
+        mem.write_memory(0x612,0xaa); //TAX
+        mem.write_memory(0x613,0x01); //ORA
+        mem.write_memory(0x614,0x03); //($03,X)
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..11{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+        assert_eq!(cpu.A, 0x31);
+        assert_eq!(cpu.X, 0x01);
+        assert_eq!(cpu.Y, 0x00);
+        assert_eq!(mem.read_memory(0), 0x20);
+        assert_eq!(mem.read_memory(1), 0x10);
+        assert_eq!(mem.read_memory(2), 0x30);
+        assert_eq!(mem.read_memory(3), 0x00);
+        assert_eq!(mem.read_memory(4), 0x02);
+    }
+
+    #[test]
+    fn test2(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600,0xa9); //LDA
+        mem.write_memory(0x601,0xc0); //#$c0
+        mem.write_memory(0x602,0xaa); //TAX
+        mem.write_memory(0x603,0xe8); //INX
+        mem.write_memory(0x604,0x69); //ADC
+        mem.write_memory(0x605,0xc4); //#$c4
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..4{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+        assert_eq!(cpu.A, 0x84);
+        assert_eq!(cpu.X, 0xc1);
+        assert_eq!(cpu.Y, 0x00);
+        assert!(cpu.P.get_N());
+        assert!(cpu.P.get_C());
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_BNE(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa9); //LDA
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xc9); //CMP
+        mem.write_memory(0x603, 0x02); //#$02
+        mem.write_memory(0x604, 0xd0); //BNE
+        mem.write_memory(0x605, 0x02); //+2
+        mem.write_memory(0x606, 0x85); //STA
+        mem.write_memory(0x607, 0x22); //$22
+        mem.write_memory(0x608, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..4{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x01);
+        assert_eq!(cpu.X, 0x00);
+        assert_eq!(cpu.Y, 0x00);
+        assert!(cpu.P.get_N());
+        assert!(!cpu.P.get_C());
+        assert!(!cpu.P.get_Z());
+
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_BNE2(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa9); //LDA
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xc9); //CMP
+        mem.write_memory(0x603, 0x01); //#$02
+        mem.write_memory(0x604, 0xd0); //BNE
+        mem.write_memory(0x605, 0x02); //+2
+        mem.write_memory(0x606, 0x85); //STA
+        mem.write_memory(0x607, 0x22); //$22
+        mem.write_memory(0x608, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..4{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x01);
+        assert_eq!(cpu.X, 0x00);
+        assert_eq!(cpu.Y, 0x00);
+        assert_eq!(mem.read_memory(0x22), 0x01);
+        assert!(!cpu.P.get_N());
+        assert!(cpu.P.get_C());
+        assert!(cpu.P.get_Z());
+    }
+
+    #[test]
+    fn test_ind_jmp(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa9); //LDA
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0x85); //STA
+        mem.write_memory(0x603, 0xf0); //$f0
+        mem.write_memory(0x604, 0xa9); //LDA
+        mem.write_memory(0x605, 0xcc); //#$cc
+        mem.write_memory(0x606, 0x85); //STA
+        mem.write_memory(0x607, 0xf1); //$f1
+        mem.write_memory(0x608, 0x6c); //JMP
+        mem.write_memory(0x609, 0xf0); //($f0)
+        mem.write_memory(0x60a, 0x00); //($00) //($00f0)
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..5{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.PC, 0xcc01);
+    }
+
+    #[test]
+    fn test_ind_jmp_page_wrap(){
+        let mut mem = Memory::new(8*1024);
+
+        mem.write_memory(0x600, 0x6c); //JMP
+        mem.write_memory(0x601, 0xff);
+        mem.write_memory(0x602, 0x10); //($10ff)
+        mem.write_memory(0x10ff, 0x34);
+        mem.write_memory(0x1000, 0x12); //NMOS takes the high byte from the same page
+        mem.write_memory(0x1100, 0x56); //65C02 carries into the next page
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.PC, 0x1234);
+        assert_eq!(cpu.last_instruction_cycles(), 5);
+
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+        cpu.reset_at(0x0600);
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.PC, 0x5634);
+        assert_eq!(cpu.last_instruction_cycles(), 6);
+    }
+
+    #[test]
+    fn test_zero_page_pointer_wrap(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa2); //LDX
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xa1); //LDA
+        mem.write_memory(0x603, 0xfe); //($fe,X) pointer at $ff
+        mem.write_memory(0x604, 0xa0); //LDY
+        mem.write_memory(0x605, 0x02); //#$02
+        mem.write_memory(0x606, 0xb1); //LDA
+        mem.write_memory(0x607, 0xff); //($ff),Y
+        mem.write_memory(0x608, 0xa2); //LDX
+        mem.write_memory(0x609, 0x10); //#$10
+        mem.write_memory(0x60a, 0xa1); //LDA
+        mem.write_memory(0x60b, 0xf0); //($f0,X) pointer $f0+$10 wraps to $00
+
+        mem.write_memory(0x00ff, 0x00);
+        mem.write_memory(0x0000, 0x08); //high byte comes from $00 not $0100
+        mem.write_memory(0x0001, 0x09);
+        mem.write_memory(0x0100, 0x02);
+        mem.write_memory(0x0800, 0x11);
+        mem.write_memory(0x0802, 0x22);
+        mem.write_memory(0x0908, 0x33);
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        cpu.run_single(&mut mem).unwrap();
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.A, 0x11);
+        cpu.run_single(&mut mem).unwrap();
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.A, 0x22);
+        cpu.run_single(&mut mem).unwrap();
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.A, 0x33);
+    }
+
+    #[test]
+    fn test_lda_indx(){
+         let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa2); //LDX
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xa9); //LDA
+        mem.write_memory(0x603, 0x05); //#$05
+        mem.write_memory(0x604, 0x85); //STA
+        mem.write_memory(0x605, 0x01); //$01
+        mem.write_memory(0x606, 0xa9); //LDA
+        mem.write_memory(0x607, 0x07); //#$07
+        mem.write_memory(0x608, 0x85); //STA
+        mem.write_memory(0x609, 0x02); //$02
+        mem.write_memory(0x60a, 0xa0); //LDY
+        mem.write_memory(0x60b, 0x0a); //#$0a
+        mem.write_memory(0x60c, 0x8c); //STY
+        mem.write_memory(0x60d, 0x05); //
+        mem.write_memory(0x60e, 0x07); //$0705
+        mem.write_memory(0x60f, 0xa1); //LDA
+        mem.write_memory(0x610, 0x00); //$(00,X)
+        mem.write_memory(0x611, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..9{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x0a);
+        assert_eq!(cpu.X, 0x01);
+        assert_eq!(cpu.Y, 0x0a);
+        assert_eq!(mem.read_memory(0x0705), 0x0a);
+        assert!(!cpu.P.get_N());
+        assert!(!cpu.P.get_C());
+        assert!(!cpu.P.get_Z());
+
+    }
+
+    #[test]
+    fn test_lda_indy(){
+         let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa0); //LDY
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xa9); //LDA
+        mem.write_memory(0x603, 0x03); //#$03
+        mem.write_memory(0x604, 0x85); //STA
+        mem.write_memory(0x605, 0x01); //$01
+        mem.write_memory(0x606, 0xa9); //LDA
+        mem.write_memory(0x607, 0x07); //#$07
+        mem.write_memory(0x608, 0x85); //STA
+        mem.write_memory(0x609, 0x02); //$02
+        mem.write_memory(0x60a, 0xa2); //LDX
+        mem.write_memory(0x60b, 0x0a); //#$0a
+        mem.write_memory(0x60c, 0x8e); //STX
+        mem.write_memory(0x60d, 0x04); //
+        mem.write_memory(0x60e, 0x07); //$0704
+        mem.write_memory(0x60f, 0xb1); //LDA
+        mem.write_memory(0x610, 0x01); //$(01),Y
+        mem.write_memory(0x611, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..9{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x0a);
+        assert_eq!(cpu.X, 0x0a);
+        assert_eq!(cpu.Y, 0x01);
+        assert_eq!(mem.read_memory(0x0704), 0x0a);
+        assert!(!cpu.P.get_N());
+        assert!(!cpu.P.get_C());
+        assert!(!cpu.P.get_Z());
+
+    }
+
+        #[test]
+    fn test_stack(){
+         let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa2); //LDX
+        mem.write_memory(0x601, 0x00); //#$01
+        mem.write_memory(0x602, 0xa0); //LDY
+        mem.write_memory(0x603, 0x00); //#$00
+        mem.write_memory(0x604, 0x8a); //TXA
+        mem.write_memory(0x605, 0x99); //STA
+        mem.write_memory(0x606, 0x00);
+        mem.write_memory(0x607, 0x02); //$0200,Y
+        mem.write_memory(0x608, 0x48); //PHA
+        mem.write_memory(0x609, 0xe8); //INX
+        mem.write_memory(0x60a, 0xc8); //INY
+        mem.write_memory(0x60b, 0xc0); //CPY
+        mem.write_memory(0x60c, 0x10); //#$10
+        mem.write_memory(0x60d, 0xd0); //BNE
+        mem.write_memory(0x60e, 0xf5); //$0604
+        mem.write_memory(0x60f, 0x68); //PLA
+        mem.write_memory(0x610, 0x99); //STA
+        mem.write_memory(0x611, 0x00);
+        mem.write_memory(0x612, 0x02); //$0200,Y
+        mem.write_memory(0x613, 0xc8); //INY
+        mem.write_memory(0x614, 0xc0); //CPY
+        mem.write_memory(0x615, 0x20); //#$20
+        mem.write_memory(0x616, 0xd0); //BNE
+        mem.write_memory(0x617, 0xf7); //$060f
+        mem.write_memory(0x618, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        for _i in 0..195{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        println!("CPU: {:?}", cpu);
+
+        assert_eq!(cpu.A, 0x00);
+        assert_eq!(cpu.X, 0x10);
+        assert_eq!(cpu.Y, 0x20);
+        assert!(!cpu.P.get_N());
+        assert!(cpu.P.get_C());
+        assert!(cpu.P.get_Z());
+
+    }
+
+    #[test]
+    fn test_jmp(){
+         let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa9); //LDA
+        mem.write_memory(0x601, 0x03); //#$03
+        mem.write_memory(0x602, 0x4c); //JMP
+        mem.write_memory(0x603, 0x08);
+        mem.write_memory(0x604, 0x06); //$0608
+        mem.write_memory(0x605, 0x00); //BRK
+        mem.write_memory(0x606, 0x00); //BRK
+        mem.write_memory(0x607, 0x00); //BRK
+        mem.write_memory(0x608, 0x8d); //STA
+        mem.write_memory(0x609, 0x00);
+        mem.write_memory(0x60a, 0x02); //$0200
+        mem.write_memory(0x60b, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..4{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x03);
+        assert_eq!(cpu.X, 0x00);
+        assert_eq!(cpu.Y, 0x00);
+        assert!(!cpu.P.get_N());
+        assert!(!cpu.P.get_C());
+        assert!(!cpu.P.get_Z());
+        assert_eq!(mem.read_memory(0x0200), 0x03);
+    }
+
+    #[test]
+    fn test_jsr_rts(){
+         let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0x20); //JSR
+        mem.write_memory(0x601, 0x09); //
+        mem.write_memory(0x602, 0x06); //$0609
+        mem.write_memory(0x603, 0x20); //JSR
+        mem.write_memory(0x604, 0x0c); //
+        mem.write_memory(0x605, 0x06); //$060c
+        mem.write_memory(0x606, 0x20); //JSR
+        mem.write_memory(0x607, 0x12); //
+        mem.write_memory(0x608, 0x06); //$0612
+        mem.write_memory(0x609, 0xa2); //LDX
+        mem.write_memory(0x60a, 0x00); //#$00
+        mem.write_memory(0x60b, 0x60); //RTS
+        mem.write_memory(0x60c, 0xe8); //INX
+        mem.write_memory(0x60d, 0xe0); //CPX
+        mem.write_memory(0x60e, 0x05); //#$05
+        mem.write_memory(0x60f, 0xd0); //BNE
+        mem.write_memory(0x610, 0xfb); //$060c
+        mem.write_memory(0x611, 0x60); //RTS
+        mem.write_memory(0x612, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        println!("CPU: {:?}", cpu);
+
+        for _i in 0..22{
+            match cpu.run_single(&mut mem) {
+                Ok(_) => {},
+                Err(e) => {
+                    cpu.show_cpu_debug();
+                    println!("Error: {}", e);
+                    break;
+                }
+            };
+        }
+
+        assert_eq!(cpu.A, 0x00);
+        assert_eq!(cpu.X, 0x05);
+        assert_eq!(cpu.Y, 0x00);
+        assert!(!cpu.P.get_N());
+        assert!(cpu.P.get_C());
+        assert!(cpu.P.get_Z());
+    }
+
+    #[test]
+    fn test_cycles(){
+        let mut mem = Memory::new(64*1024);
+
+        mem.write_memory(0x600, 0xa2); //LDX
+        mem.write_memory(0x601, 0x01); //#$01
+        mem.write_memory(0x602, 0xbd); //LDA
+        mem.write_memory(0x603, 0x00);
+        mem.write_memory(0x604, 0x02); //$0200,X no page cross
+        mem.write_memory(0x605, 0xbd); //LDA
+        mem.write_memory(0x606, 0xff);
+        mem.write_memory(0x607, 0x02); //$02ff,X page cross
+        mem.write_memory(0x608, 0x9d); //STA
+        mem.write_memory(0x609, 0xff);
+        mem.write_memory(0x60a, 0x02); //$02ff,X store never pays extra
+        mem.write_memory(0x60b, 0xd0); //BNE
+        mem.write_memory(0x60c, 0x00); //+0 not taken, Z set by LDA #$00 at $0300
+        mem.write_memory(0x60d, 0xf0); //BEQ
+        mem.write_memory(0x60e, 0x00); //+0 taken same page
+        mem.write_memory(0x60f, 0xf0); //BEQ
+        mem.write_memory(0x610, 0x80); //-128 taken crossing to $0591
+        mem.write_memory(0x591, 0x00); //BRK
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        let expected = [2, 4, 5, 5, 2, 3, 4, 7];
+        for cycles in expected{
+            cpu.run_single(&mut mem).unwrap();
+            assert_eq!(cpu.last_instruction_cycles(), cycles);
+        }
+        assert_eq!(cpu.cycles(), expected.iter().sum::<u8>() as u64);
+
+        cpu.P.set_I(false);
+        assert_eq!(cpu.interrupt(InterruptType::INT, &mut mem), 7);
+        cpu.P.set_I(true);
+        assert_eq!(cpu.interrupt(InterruptType::INT, &mut mem), 0);
+        assert_eq!(cpu.cycles(), expected.iter().sum::<u8>() as u64 + 7);
+    }
+
+    #[test]
+    fn test_undocumented(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x10, 0x81);
+        mem.write_memory(0x600, 0xa7); //LAX
+        mem.write_memory(0x601, 0x10); //$10
+        mem.write_memory(0x602, 0x07); //SLO
+        mem.write_memory(0x603, 0x10); //$10
+        mem.write_memory(0x604, 0xa9); //LDA
+        mem.write_memory(0x605, 0x0f); //#$0f
+        mem.write_memory(0x606, 0x87); //SAX
+        mem.write_memory(0x607, 0x11); //$11
+        mem.write_memory(0x608, 0xc7); //DCP
+        mem.write_memory(0x609, 0x11); //$11
+        mem.write_memory(0x60a, 0xcb); //SBX
+        mem.write_memory(0x60b, 0x02); //#$02
+        mem.write_memory(0x60c, 0xe7); //ISC
+        mem.write_memory(0x60d, 0x12); //$12
+        mem.write_memory(0x60e, 0x0b); //ANC
+        mem.write_memory(0x60f, 0xf0); //#$f0
+        mem.write_memory(0x610, 0x04); //NOP
+        mem.write_memory(0x611, 0x10); //$10
+        mem.write_memory(0x612, 0x02); //JAM
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        for _i in 0..9{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Jam { pc: 0x612, opcode: 0x02 }));
+
+        assert_eq!(mem.read_memory(0x10), 0x02);
+        assert_eq!(mem.read_memory(0x11), 0x00);
+        assert_eq!(mem.read_memory(0x12), 0x01);
+        assert_eq!(cpu.X, 0xff);
+        assert_eq!(cpu.A, 0x00);
+        assert!(cpu.P.get_Z());
+        assert!(!cpu.P.get_C());
+        assert!(cpu.is_jammed());
+        assert_eq!(cpu.PC, 0x613);
+
+        let cycles = cpu.cycles();
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Jam { pc: 0x612, opcode: 0x02 }));
+        assert_eq!(cpu.PC, 0x613);
+        assert_eq!(cpu.cycles(), cycles + 1);
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.set_trap_undocumented(true);
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::IllegalOpcode { pc: 0x600, opcode: 0xa7 }));
+        assert_eq!(cpu.PC, 0x600);
+
+        // Opcodes the 65C02 defines as NOPs are documented there
+        mem.write_memory(0x700, 0x44); //NOP $00
+        mem.write_memory(0x701, 0x00);
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+        cpu.reset_at(0x0700);
+        cpu.set_trap_undocumented(true);
+        assert_eq!(cpu.run_single(&mut mem), Ok(0x700));
+        assert_eq!(cpu.PC, 0x702);
+        assert_eq!(cpu.cycles(), 3);
+    }
+
+    #[test]
+    fn test_bus_fault(){
+        let mut mem = Memory::new(4*1024);
+        mem.write_memory(0x600, 0xad); //LDA
+        mem.write_memory(0x601, 0x00); //$2000
+        mem.write_memory(0x602, 0x20);
+        mem.write_memory(0x603, 0xea); //NOP
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let error = cpu.run_single(&mut mem).unwrap_err();
+        assert_eq!(error, CpuError::BusFault { pc: 0x600, address: 0x2000 });
+        assert_eq!(error.pc(), 0x600);
+        assert_eq!(cpu.run_single(&mut mem).unwrap(), 0x603);
+    }
+
+    #[test]
+    fn test_unstable_store(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa2); //LDX
+        mem.write_memory(0x601, 0x0e); //#$0e
+        mem.write_memory(0x602, 0xa0); //LDY
+        mem.write_memory(0x603, 0x01); //#$01
+        mem.write_memory(0x604, 0x9e); //SHX
+        mem.write_memory(0x605, 0x00);
+        mem.write_memory(0x606, 0x02); //$0200,Y no page cross: X & $03
+        mem.write_memory(0x607, 0x9e); //SHX
+        mem.write_memory(0x608, 0xff);
+        mem.write_memory(0x609, 0x08); //$08ff,Y page cross: X & $09 replaces the high byte
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+
+        for _i in 0..4{
+            cpu.run_single(&mut mem).unwrap();
+        }
+
+        assert_eq!(mem.read_memory(0x0201), 0x02);
+        assert_eq!(mem.read_memory(0x0800), 0x08);
+        assert_eq!(mem.read_memory(0x0900), 0x00);
+        assert_eq!(cpu.last_instruction_cycles(), 5);
+    }
+
+    struct BusLog{
+        memory: Memory,
+        log: Vec<(u16, u8, bool)>, //address, value, write
+    }
+
+    impl Memory6502 for BusLog{
+        fn write_memory(&mut self, address: u16, value: u8){
+            self.log.push((address, value, true));
+            self.memory.write_memory(address, value);
+        }
+
+        fn read_memory(&mut self, address: u16) -> u8{
+            let value = self.memory.read_memory(address);
+            self.log.push((address, value, false));
+            value
+        }
+
+        fn read_memory_word(&mut self, address: u16) -> u16{
+            self.memory.read_memory_word(address)
+        }
+
+        fn peek(&self, address: u16) -> u8{
+            self.memory.peek(address)
+        }
+
+        fn poke(&mut self, address: u16, value: u8){
+            self.memory.poke(address, value)
+        }
+    }
+
+    #[test]
+    fn test_bus_accesses(){
+        let mut mem = BusLog{ memory: Memory::new(64*1024), log: Vec::new() };
+
+        mem.memory.write_memory(0x600, 0xee); //INC
+        mem.memory.write_memory(0x601, 0x19);
+        mem.memory.write_memory(0x602, 0xd0); //$d019
+        mem.memory.write_memory(0x603, 0xbd); //LDA
+        mem.memory.write_memory(0x604, 0xf0);
+        mem.memory.write_memory(0x605, 0x02); //$02f0,X
+        mem.memory.write_memory(0x606, 0x9d); //STA
+        mem.memory.write_memory(0x607, 0x00);
+        mem.memory.write_memory(0x608, 0x02); //$0200,X
+        mem.memory.write_memory(0xd019, 0x41);
+
+        let mut cpu = CPU6502::new();
+
+        cpu.reset_at(0x0600);
+        cpu.X = 0x20;
+
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(mem.log, vec![(0x0600, 0xee, false), (0x0601, 0x19, false), (0x0602, 0xd0, false),
+            (0xd019, 0x41, false), (0xd019, 0x41, true), (0xd019, 0x42, true)]);
+
+        mem.log.clear();
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(mem.log[3], (0x0210, 0x00, false)); //high byte not fixed yet
+        assert_eq!(mem.log[4], (0x0310, 0x00, false));
+
+        mem.log.clear();
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(mem.log[3], (0x0220, 0x00, false)); //stores always read first
+        assert_eq!(mem.log[4], (0x0220, 0x00, true));
+    }
+
+    #[test]
+    fn test_bus_cycles(){
+        for ins in 0..=255u8{
+            if ins & 0x1f == 0x12 || (ins & 0x0f == 0x02 && ins < 0x80){
+                continue; //JAM
+            }
+            for index in [0x00, 0xff]{
+                let mut mem = BusLog{ memory: Memory::new(64*1024), log: Vec::new() };
+                mem.memory.write_memory(0x600, ins);
+                mem.memory.write_memory(0x601, 0x80);
+                mem.memory.write_memory(0x602, 0x02);
+                mem.memory.write_memory(0x81, 0x02);
+
+                let mut cpu = CPU6502::new();
+                cpu.reset_at(0x0600);
+                cpu.X = index;
+                cpu.Y = index;
+
+                let _ = cpu.run_single(&mut mem); //jumps may land anywhere, only the bus matters
+                assert_eq!(mem.log.len(), cpu.last_instruction_cycles() as usize, "opcode {:#04x} index {:#04x}", ins, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cmos(){
+        let mut mem = Memory::new(64*1024);
+
+        mem.write_memory(0x20, 0x00);
+        mem.write_memory(0x21, 0x03); //pointer to $0300
+        mem.write_memory(0x0300, 0x5a);
+        mem.write_memory(0x0400, 0x34);
+        mem.write_memory(0x0401, 0x12); //$1234 at $0400
+
+        mem.write_memory(0x600, 0x80); //BRA
+        mem.write_memory(0x601, 0x01); //+1
+        mem.write_memory(0x602, 0x02); //not executed
+        mem.write_memory(0x603, 0xb2); //LDA
+        mem.write_memory(0x604, 0x20); //($20)
+        mem.write_memory(0x605, 0x1a); //INC A
+        mem.write_memory(0x606, 0xda); //PHX
+        mem.write_memory(0x607, 0x7a); //PLY
+        mem.write_memory(0x608, 0x64); //STZ
+        mem.write_memory(0x609, 0x30); //$30
+        mem.write_memory(0x60a, 0x04); //TSB
+        mem.write_memory(0x60b, 0x30); //$30
+        mem.write_memory(0x60c, 0x89); //BIT
+        mem.write_memory(0x60d, 0x00); //#$00
+        mem.write_memory(0x60e, 0xf7); //SMB7
+        mem.write_memory(0x60f, 0x31); //$31
+        mem.write_memory(0x610, 0xff); //BBS7
+        mem.write_memory(0x611, 0x31); //$31
+        mem.write_memory(0x612, 0x01); //+1
+        mem.write_memory(0x613, 0xdb); //STP not executed
+        mem.write_memory(0x614, 0xa2); //LDX
+        mem.write_memory(0x615, 0x02); //#$02
+        mem.write_memory(0x616, 0x7c); //JMP
+        mem.write_memory(0x617, 0xfe);
+        mem.write_memory(0x618, 0x03); //($03fe,X)
+        mem.write_memory(0x1234, 0xcb); //WAI
+        mem.write_memory(0x1235, 0xdb); //STP
+
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+
+        cpu.reset_at(0x0600);
+
+        for _i in 0..12{
+            cpu.run_single(&mut mem).unwrap();
+        }
+
+        assert_eq!(cpu.A, 0x5b);
+        assert_eq!(cpu.Y, 0x00);
+        assert_eq!(cpu.X, 0x02);
+        assert_eq!(mem.read_memory(0x30), 0x5b);
+        assert_eq!(mem.read_memory(0x31), 0x80);
+        assert_eq!(cpu.PC, 0x1234);
+
+        cpu.run_single(&mut mem).unwrap(); //WAI
+        let cycles = cpu.cycles();
+        assert_eq!(cpu.run_single(&mut mem).unwrap(), 0x1235);
+        assert_eq!(cpu.cycles(), cycles + 1);
+        assert_eq!(cpu.interrupt(InterruptType::INT, &mut mem), 0); //masked, WAI falls through
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Jam { pc: 0x1235, opcode: 0xdb })); //STP
+        assert!(cpu.is_jammed());
+    }
+
+    #[test]
+    fn test_opcode_table(){
+        let mut mem = Memory::new(4*1024);
+
+        mem.write_memory(0x600, 0xa9); //LDA
+        mem.write_memory(0x601, 0x20); //#$20
+        mem.write_memory(0x602, 0x80); //BRA
+        mem.write_memory(0x603, 0x00); //+0
+        mem.write_memory(0x604, 0x0f); //BBR0
+        mem.write_memory(0x605, 0x10); //$10
+        mem.write_memory(0x606, 0x00); //+0
+
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+        cpu.enable_trace(1);
+
+        cpu.reset_at(0x0600);
+
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.get_last_state().disassemble().to_string(), "LDA #$20");
+        assert_eq!(cpu.last_instruction_cycles(), 2);
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.get_last_state().disassemble().to_string(), "BRA $0604");
+        assert_eq!(cpu.last_instruction_cycles(), 3);
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.get_last_state().disassemble().to_string(), "BBR0 $10,$0607");
+        assert_eq!(cpu.last_instruction_cycles(), 6);
+
+        let cpu = CPU6502::new();
+        assert_eq!(CPUState::new(&cpu, 0x1f).disassemble().to_string(), "SLO $0000,X");
+    }
+
+    #[test]
+    fn test_disassembler(){
+        let mut mem = Memory::new(64*1024);
+
+        let program = [
+            0xa9, 0x12,       //LDA #$12
+            0xbd, 0x34, 0x12, //LDA $1234,X
+            0xb1, 0x12,       //LDA ($12),Y
+            0xa1, 0x12,       //LDA ($12,X)
+            0x96, 0x80,       //STX $80,Y
+            0x6c, 0xfc, 0xff, //JMP ($fffc)
+            0x0a,             //ASL A
+            0xd0, 0xfe,       //BNE *
+            0x20, 0x00, 0x06, //JSR $0600
+            0x60,             //RTS
+            0x02,             //JAM
+        ];
+        for (i, b) in program.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let lines: Vec<String> = disassemble_range(&mem, 0x0600..=0x0615, CpuVariant::Nmos6502).iter().map(|i| i.to_string()).collect();
+        assert_eq!(lines, vec!["LDA #$12", "LDA $1234,X", "LDA ($12),Y", "LDA ($12,X)", "STX $80,Y", "JMP ($fffc)",
+            "ASL A", "BNE $060f", "JSR $0600", "RTS", "JAM"]);
+
+        let bne = disassemble(&mem, 0x060f, CpuVariant::Nmos6502);
+        assert_eq!(bne.length, 2);
+        assert_eq!(bne.target, Some(0x060f));
+        assert_eq!(bne.listing(), "060f  d0 fe     BNE $060f");
+
+        let cmos = disassemble(&mem, 0x0615, CpuVariant::Cmos65C02);
+        assert_eq!(cmos.to_string(), "NOP #$00");
+        mem.write_memory(0x0700, 0x8f); //BBS0
+        mem.write_memory(0x0701, 0x10); //$10
+        mem.write_memory(0x0702, 0x80); //-128
+        let bbs = disassemble(&mem, 0x0700, CpuVariant::Cmos65C02);
+        assert_eq!(bbs.to_string(), "BBS0 $10,$0683");
+        assert_eq!(bbs.length, 3);
+    }
+
+    #[test]
+    fn test_assembler(){
+        let source = "
+            screen = $0400
+            count = 3          ; constants may be used before code
+            start:  ldx #count
+            loop:   lda message-1,x
+                    sta screen-1,x
+                    dex
+                    bne loop
+                    lda #<table
+                    sta $fb
+                    lda #>table
+                    sta $fc
+                    ldy #1
+                    lda ($fb),y
+                    jsr done
+                    jmp (vector)
+            done:   rts
+            message: .byte 'a', \"bc\"
+            table:  .byte %0101, 2*3+1
+            vector: .word start, done
+        ";
+        let code = assemble(source, 0x0600, CpuVariant::Nmos6502).unwrap();
+        assert_eq!(&code[..7], &[0xa2, 0x03, 0xbd, 0x1d, 0x06, 0x9d, 0xff]);
+        assert_eq!(&code[0x1e..], &[0x61, 0x62, 0x63, 0x05, 0x07, 0x00, 0x06, 0x1d, 0x06]);
+
+        let mut mem = Memory::new(4*1024);
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        for _i in 0..22{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(mem.read_memory(0x0400), 0x61);
+        assert_eq!(mem.read_memory(0x0402), 0x63);
+        assert_eq!(cpu.A, 0x07);
+        assert_eq!(cpu.PC, 0x0600);
+
+        assert_eq!(assemble("lda nowhere", 0, CpuVariant::Nmos6502).unwrap_err().line, 1);
+        assert_eq!(assemble("nop\nbne $1000", 0, CpuVariant::Nmos6502).unwrap_err().line, 2);
+        assert!(assemble("stz $10", 0, CpuVariant::Nmos6502).is_err());
+        assert_eq!(assemble("stz $10\nbbs7 $10,*", 0, CpuVariant::Cmos65C02).unwrap(), vec![0x64, 0x10, 0xff, 0x10, 0xfd]);
+        assert_eq!(assemble("lda #' '\ncmp #' ' + 1\n.byte ' ', ';'", 0, CpuVariant::Nmos6502).unwrap(), vec![0xa9, 0x20, 0xc9, 0x21, 0x20, 0x3b]);
+        for source in [".word $7fffffffffffffff+1", ".word -$7fffffffffffffff-2", ".word 1<<64", "lda #-(-$7fffffffffffffff-1)", ".byte 2*$4000000000000000"]{
+            assert!(assemble(source, 0, CpuVariant::Nmos6502).unwrap_err().to_string().contains("Overflow"), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_assembler_round_trip(){
+        for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02]{
+            for ins in 0..=255u8{
+                let mut mem = Memory::new(64*1024);
+                mem.write_memory(0x0600, ins);
+                mem.write_memory(0x0601, 0x34);
+                mem.write_memory(0x0602, 0x12);
+                let instruction = disassemble(&mem, 0x0600, variant);
+                let code = assemble(&instruction.to_string(), 0x0600, variant).unwrap();
+                if code[0] != ins{
+                    // Undocumented duplicates assemble to their documented twin
+                    let mut bytes = [0; 3];
+                    bytes[..code.len()].copy_from_slice(&code);
+                    assert_eq!(Instruction::decode(0x0600, bytes, variant).to_string(), instruction.to_string());
+                    continue;
+                }
+                assert_eq!(code, instruction.bytes[..instruction.length as usize], "{}", instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cmos_decimal(){
+        let mut mem = Memory::new(64*1024);
+
+        mem.write_memory(0xfffe, 0x00);
+        mem.write_memory(0xffff, 0x07); //BRK vector $0700
+        mem.write_memory(0x600, 0xf8); //SED
+        mem.write_memory(0x601, 0x18); //CLC
+        mem.write_memory(0x602, 0xa9); //LDA
+        mem.write_memory(0x603, 0x99); //#$99
+        mem.write_memory(0x604, 0x69); //ADC
+        mem.write_memory(0x605, 0x01); //#$01
+        mem.write_memory(0x606, 0x00); //BRK
+
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+
+        cpu.reset_at(0x0600);
+
+        for _i in 0..4{
+            cpu.run_single(&mut mem).unwrap();
+        }
+
+        assert_eq!(cpu.A, 0x00);
+        assert!(cpu.P.get_C());
+        assert!(cpu.P.get_Z());
+        assert!(!cpu.P.get_N());
+        assert_eq!(cpu.last_instruction_cycles(), 3);
+
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.PC, 0x0700);
+        assert!(!cpu.P.get_D());
+    }
+
+    #[test]
+    fn test_cmos_bus_cycles(){
+        let mut mem = Memory::new(64*1024);
+        let code = assemble("
+                    ldx #1
+                    asl $0200,x
+                    asl $02ff,x
+                    sed
+                    adc #$01
+                    adc $0201
+        ", 0x0600, CpuVariant::Cmos65C02).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new_variant(CpuVariant::Cmos65C02);
+        cpu.reset_at(0x0600);
+        cpu.enable_trace(1);
+        // Every counted cycle is a bus access: no fix up read without a page cross, the decimal cycle reads the operand again
+        for (cycles, last) in [(2, 0x0601), (6, 0x0201), (7, 0x0300), (2, 0x0608), (3, 0x060a), (5, 0x0201)]{
+            cpu.run_single(&mut mem).unwrap();
+            let state = cpu.get_last_state();
+            assert_eq!(cpu.last_instruction_cycles(), cycles);
+            assert_eq!(state.bus_cycles().len(), cycles as usize);
+            if cycles > 2{
+                assert_eq!(state.bus_cycles().last().unwrap().address, last);
+            }
+        }
+    }
+
+    #[test]
+    fn test_all() -> Result<(), crate::cpu6502::CpuError>{
+        let mut mem = Memory::from_file("./tests/6502_functional_test.bin").unwrap();
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0400);
+        cpu.enable_trace(32);
+
+        let mut cnt = 0;
+
+        loop{
+            cnt += 1;
+            let pc = cpu.run_single(&mut mem)?;
+            if cpu.PC == pc{ //The test traps itself in a jmp * or branch to itself
+                if pc != 0x3469{ //This test program loops here on success
+                    println!("Trapped after {} instructions", cnt);
+                    cpu.show_cpu_debug();
+                }
+                assert_eq!(pc, 0x3469);
+                return Ok(());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decimal() -> Result<(), crate::cpu6502::CpuError>{
+        let mut mem = Memory::from_file("./tests/6502_decimal_test.bin").unwrap();
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0200);
+        cpu.enable_trace(32);
+
+        loop{
+            let pc = cpu.run_single(&mut mem)?;
+            if cpu.PC == pc{
+                assert_eq!(pc, 0x024b); //Test ends looping at DONE, ERROR is 0 on success
+                let error = mem.read_memory(0x000b);
+                if error != 0{
+                    println!("N1={:#04x} N2={:#04x} C={}", mem.read_memory(0x00), mem.read_memory(0x01), cpu.Y);
+                    cpu.show_cpu_debug();
+                }
+                assert_eq!(error, 0);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Feedback port at $bffc driving the lines, bit 0 holds IRQ low and bit 1 holds NMI low.
+    struct InterruptPort{
+        memory: Memory,
+        lines: InterruptController,
+        accesses: usize,
+        irq_at: usize, //bus access that raises IRQ, on top of the port
+        nmi_at: usize, //bus access that raises NMI, on top of the port
+        rdy_at: usize, //bus access after which RDY goes low
+        rdy_cycles: usize, //for how many cycles
+        so_at: usize, //bus access that pulls SO low
+    }
+
+    impl InterruptPort{
+        fn new() -> Self{
+            InterruptPort { memory: Memory::new(64*1024), lines: InterruptController::new(), accesses: 0, irq_at: usize::MAX, nmi_at: usize::MAX,
+                rdy_at: usize::MAX, rdy_cycles: 0, so_at: usize::MAX }
+        }
+
+        fn access(&mut self){
+            self.accesses += 1;
+            if self.accesses == self.irq_at{
+                self.lines.assert_irq(1);
+            }
+            if self.accesses == self.nmi_at{
+                self.lines.assert_nmi(1);
+            }
+        }
+    }
+
+    impl Memory6502 for InterruptPort{
+        fn write_memory(&mut self, address: u16, value: u8){
+            self.memory.write_memory(address, value);
+            if address == 0xbffc{
+                self.lines.set_irq(0, value & 0x01 != 0);
+                self.lines.set_nmi(0, value & 0x02 != 0);
+            }
+            self.access();
+        }
+
+        fn read_memory(&mut self, address: u16) -> u8{
+            self.access();
+            self.memory.read_memory(address)
+        }
+
+        fn read_memory_word(&mut self, address: u16) -> u16{
+            self.memory.read_memory_word(address)
+        }
+
+        fn peek(&self, address: u16) -> u8{
+            self.memory.peek(address)
+        }
+
+        fn poke(&mut self, address: u16, value: u8){
+            self.memory.poke(address, value)
+        }
+
+        fn interrupt_controller(&self) -> Option<&InterruptController>{
+            Some(&self.lines)
+        }
+
+        fn rdy(&mut self) -> bool{
+            if self.accesses >= self.rdy_at && self.rdy_cycles > 0{
+                self.rdy_cycles -= 1;
+                return false;
+            }
+            true
+        }
+
+        fn so(&self) -> bool{
+            self.accesses >= self.so_at
+        }
+    }
+
+    #[test]
+    fn test_interrupts(){
+        let code = assemble(include_str!("../../tests/interrupt_test.s"), 0x0400, CpuVariant::Nmos6502).unwrap();
+        let mut mem = InterruptPort::new();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0400 + i as u16, *b);
+        }
+        mem.write_memory(0xfffa, 0x06); //NMI $0406
+        mem.write_memory(0xfffb, 0x04);
+        mem.write_memory(0xfffe, 0x03); //IRQ $0403
+        mem.write_memory(0xffff, 0x04);
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0400);
+        cpu.enable_trace(32);
+        cpu.set_halt_policy(HaltPolicy::HaltOnSelfLoop);
+        for _i in 0..1000{
+            if let Err(error) = cpu.run_single(&mut mem){
+                let result = mem.read_memory(0x02);
+                if result != 0{
+                    cpu.show_trace();
+                }
+                assert_eq!(result, 0, "stopped with {}", error);
+                return;
+            }
+        }
+        panic!("interrupt test did not finish");
+    }
+
+    /// Klaus Dormann's 6502_interrupt_test in its ca65 port, feedback register at $bffc with IRQ on bit 0 and NMI on bit 1.
+    /// That build leaves out the concurrent BRK+IRQ+NMI case, test_interrupts covers NMI taking over BRK.
+    #[test]
+    fn test_klaus_interrupts(){
+        let mut mem = InterruptPort::new();
+        mem.memory = Memory::from_file("./tests/6502_interrupt_test.bin").unwrap();
+        mem.write_memory(0xbffc, 0x00); //the port powers up with both lines released
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0400);
+        cpu.enable_trace(32);
+        loop{
+            let pc = cpu.run_single(&mut mem).unwrap();
+            if cpu.PC == pc{ //Traps are a jmp * or a branch to itself
+                if pc != 0x06e8{ //success after the overlapping BRK test
+                    cpu.show_cpu_debug();
+                }
+                assert_eq!(pc, 0x06e8);
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_nmi_takes_over_irq(){
+        let mut mem = InterruptPort::new();
+        mem.memory.write_memory(0x600, 0x58); //CLI
+        mem.memory.write_memory(0x601, 0xea); //NOP
+        mem.memory.write_memory(0xfffa, 0x00); //NMI $0900
+        mem.memory.write_memory(0xfffb, 0x09);
+        mem.memory.write_memory(0xfffe, 0x00); //IRQ $0800
+        mem.memory.write_memory(0xffff, 0x08);
+        mem.irq_at = 1; //taken after the NOP
+        mem.nmi_at = 7; //while the IRQ sequence pushes PC
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.P.set_I(true);
+        cpu.enable_trace(1);
+        for _i in 0..3{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.PC, 0x0900);
+        assert_eq!(cpu.get_last_state().interrupt(), Some(InterruptType::NMI));
+        assert_eq!(cpu.call_stack().frames()[0].kind, FrameKind::Nmi);
+    }
+
+    #[test]
+    fn test_irq_branch_delay(){
+        for (irq_at, ret) in [(3, 0x0604), (5, 0x0605), (6, 0x0605)]{
+            let mut mem = InterruptPort::new();
+            mem.memory.write_memory(0x600, 0x58); //CLI
+            mem.memory.write_memory(0x601, 0xd0); //BNE
+            mem.memory.write_memory(0x602, 0x01); //$0604
+            mem.memory.write_memory(0x604, 0xea); //NOP
+            mem.memory.write_memory(0x605, 0xea); //NOP
+            mem.memory.write_memory(0xfffe, 0x00); //IRQ $0800
+            mem.memory.write_memory(0xffff, 0x08);
+            mem.irq_at = irq_at; //CLI is accesses 1-2, BNE 3-5, NOP 6-7
+
+            let mut cpu = CPU6502::new();
+            cpu.reset_at(0x0600);
+            cpu.P.set_I(true);
+            while cpu.PC != 0x0800{
+                cpu.run_single(&mut mem).unwrap();
+            }
+            // A taken branch polls before its last cycle is added, an IRQ that late waits one more instruction
+            assert_eq!(mem.read_memory_word(0x01fe), ret);
+        }
+    }
+
+    #[test]
+    fn test_interrupt_lines(){
+        let mut lines = InterruptController::new();
+        lines.assert_irq(31);
+        lines.set_irq(3, true);
+        lines.release_irq(31);
+        assert!(lines.irq());
+        lines.set_irq(3, false);
+        assert!(!lines.irq());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_interrupt_source_range(){
+        InterruptController::new().assert_nmi(32);
+    }
+
+    #[test]
+    fn test_rdy_so(){
+        for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02]{
+            let mut mem = InterruptPort::new();
+            mem.memory.write_memory(0x600, 0x85); //STA
+            mem.memory.write_memory(0x601, 0x10); //$10
+            mem.memory.write_memory(0x602, 0xb8); //CLV
+            mem.memory.write_memory(0x603, 0xea); //NOP
+            mem.memory.write_memory(0x604, 0xb8); //CLV
+            mem.memory.write_memory(0x605, 0xea); //NOP
+            mem.rdy_at = 2; //RDY goes low before the write of STA
+            mem.rdy_cycles = 3;
+            mem.so_at = 7; //SO falls during the NOP
+
+            let mut cpu = CPU6502::new_variant(variant);
+            cpu.reset_at(0x0600);
+            cpu.enable_trace(4);
+            cpu.run_single(&mut mem).unwrap(); //STA
+            let stalled = if variant == CpuVariant::Nmos6502 { 0 } else { 3 };
+            assert_eq!(cpu.last_stall_cycles(), stalled);
+            assert_eq!(cpu.cycles(), 3 + stalled as u64);
+            cpu.run_single(&mut mem).unwrap(); //CLV, the NMOS part stalls on its opcode fetch
+            assert_eq!(cpu.last_stall_cycles(), 3 - stalled);
+            assert_eq!(cpu.cycles(), 8);
+            let state = cpu.get_last_state();
+            assert_eq!(state.cycles(), 3 + stalled as u64);
+            assert_eq!(state.elapsed_cycles(), 5 - stalled as u32);
+
+            assert!(!cpu.P.get_V());
+            cpu.run_single(&mut mem).unwrap(); //NOP
+            assert!(cpu.P.get_V());
+            cpu.run_single(&mut mem).unwrap(); //CLV
+            cpu.run_single(&mut mem).unwrap(); //NOP, SO is still low
+            assert!(!cpu.P.get_V());
+        }
+    }
+
+    #[test]
+    fn test_registers(){
+        let mut mem = Memory::new(4*1024);
+        mem.write_memory(0x700, 0x69); //ADC
+        mem.write_memory(0x701, 0x01); //#$01
+
+        let mut cpu = CPU6502::new();
+        cpu.enable_trace(1);
+        let mut registers = cpu.registers();
+        registers.A = 0x7f;
+        registers.PC = 0x0700;
+        registers.P.set_C(true);
+        cpu.set_registers(registers);
+        cpu.set_X(0x12);
+        cpu.set_Y(0x34);
+        cpu.set_SP(0xf0);
+        assert_eq!(cpu.get_PC(), 0x0700);
+
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.get_A(), 0x81);
+        assert_eq!((cpu.get_X(), cpu.get_Y(), cpu.get_SP(), cpu.get_PC()), (0x12, 0x34, 0xf0, 0x0702));
+        let p = cpu.get_P();
+        assert!(p.get_N() && p.get_V() && !p.get_C() && !p.get_Z());
+
+        let state = cpu.get_last_state();
+        assert_eq!(state.opcode(), 0x69);
+        assert_eq!(state.registers(), CpuRegisters { PC: 0x0700, ..cpu.registers() });
+        assert_eq!(state.operands().0, 0x01);
+        assert_eq!(state.cycles(), 0);
+
+        let mut p = StatusRegister::new(0x20);
+        p.set_I(true);
+        cpu.set_P(p);
+        assert_eq!(cpu.get_P().value(), 0x24);
+    }
+
+    static TRACED: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_trace(state: &CPUState){
+        assert_eq!(state.opcode(), 0xe8); //INX
+        TRACED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_trace(){
+        let mut mem = Memory::new(4*1024);
+        for i in 0..10{
+            mem.write_memory(0x600 + i, 0xe8); //INX
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.enable_trace(4);
+        cpu.set_trace_hook(Some(count_trace));
+        for _i in 0..6{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(TRACED.load(Ordering::SeqCst), 6);
+        assert_eq!(cpu.get_last_state().registers().PC, 0x0605);
+        assert_eq!(cpu.get_last_state().registers().X, 6);
+
+        let trace: Vec<u16> = cpu.trace.as_ref().unwrap().iter().map(|state| state.PC).collect();
+        assert_eq!(trace, vec![0x0602, 0x0603, 0x0604, 0x0605]);
+
+        for i in 0..TRACE_CAPACITY as u16 + 10{
+            mem.write_memory(0x600 + i, 0xe8); //INX
+        }
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.enable_trace(1000);
+        for _i in 0..TRACE_CAPACITY + 10{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.trace.as_ref().unwrap().iter().count(), TRACE_CAPACITY + 10);
+    }
+
+    #[test]
+    fn test_trace_log(){
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+                    jsr sub
+                    lda #$20
+                    sta $0200,x
+            sub:    ldx #1
+                    rts
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut log = TraceLog::new(Vec::new(), TraceFilter::default());
+        for _i in 0..5{
+            cpu.run_single_traced(&mut mem, &mut log).unwrap();
+        }
+        assert_eq!(log.lines(), 5);
+        let text = String::from_utf8(log.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "0600  20 08 06  JSR $0608        EA:0608 A:00 X:00 Y:00 P:34 SP:FF CYC:0");
+        assert_eq!(lines[4], "0605  9D 00 02  STA $0200,X      EA:0201 A:20 X:01 Y:00 P:34 SP:FF CYC:16");
+
+        let filter = TraceFilter { calls_only: true, ..Default::default() };
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut log = TraceLog::new(Vec::new(), filter);
+        for _i in 0..5{
+            cpu.run_single_traced(&mut mem, &mut log).unwrap();
+        }
+        assert_eq!(log.lines(), 2);
+
+        let filter = TraceFilter { ranges: vec![0x0603..=0x0607], start: Some(0x0608), stop: Some(0x0605), ..Default::default() };
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut log = TraceLog::new(Vec::new(), filter);
+        for _i in 0..7{
+            cpu.run_single_traced(&mut mem, &mut log).unwrap();
+        }
+        assert!(log.is_active()); //started again by the second JSR
+        let text = String::from_utf8(log.finish().unwrap()).unwrap();
+        let pcs: Vec<&str> = text.lines().map(|line| &line[..4]).collect();
+        assert_eq!(pcs, vec!["0603", "0605"]);
+    }
+
+    #[test]
+    fn test_trace_diff(){
+        let vice = TraceRecord::parse(".C:e5cf  A5 C6       LDA $C6        - A:00 X:00 Y:0A SP:f3 ..-..IZC   1234567").unwrap();
+        assert_eq!(vice, TraceRecord { pc: 0xe5cf, a: Some(0x00), x: Some(0x00), y: Some(0x0a), sp: Some(0xf3), p: Some(0x07), cycles: Some(1234567) });
+        let nestest = TraceRecord::parse("C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7").unwrap();
+        assert_eq!(nestest, TraceRecord { pc: 0xc000, a: Some(0x00), x: Some(0x00), y: Some(0x00), sp: Some(0xfd), p: Some(0x24), cycles: Some(7) });
+        assert_eq!(TraceRecord::parse("Loading nestest.nes"), None);
+        for line in ["", "C0", "C\u{e9}", "C\u{e9} A:00", "\u{20ac}\u{20ac}", "\u{e9}\u{e9} A:00"]{
+            assert_eq!(TraceRecord::parse(line), None);
+        }
+
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+                    jsr sub
+                    lda #$20
+                    sta $0200,x
+            sub:    ldx #1
+                    rts
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+        let reference = "\
+0600  20 08 06  JSR $0608    A:00 X:00 Y:00 P:24 SP:FF CYC:7
+0608  A2 01     LDX #$01     A:00 X:00 Y:00 P:24 SP:FD CYC:13
+060A  60        RTS          A:00 X:01 Y:00 P:24 SP:FD CYC:15
+0603  A9 20     LDA #$20     A:00 X:01 Y:00 P:24 SP:FF CYC:21
+0605  9D 00 02  STA $0200,X  A:21 X:01 Y:00 P:24 SP:FF CYC:23
+0608  A2 01     LDX #$01     A:20 X:01 Y:00 P:24 SP:FF CYC:28
+";
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut diff = TraceDiff::new(reference.as_bytes(), 1);
+        while !diff.is_finished(){
+            cpu.run_single_traced(&mut mem, &mut diff).unwrap();
+        }
+        assert_eq!(diff.matched(), 4);
+        let divergence = diff.finish().unwrap().unwrap();
+        assert_eq!(divergence.line, 5);
+        assert_eq!(divergence.reason, "A 20 expected 21");
+        assert_eq!(divergence.ours.len(), 3);
+        assert_eq!(&divergence.reference[1][..4], "0605");
+        assert_eq!(divergence.reference.len(), 3);
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut diff = TraceDiff::new(&reference.as_bytes()[..reference.find("0605").unwrap()], 1);
+        while !diff.is_finished(){
+            cpu.run_single_traced(&mut mem, &mut diff).unwrap();
+        }
+        assert!(diff.finish().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_profiler(){
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+            start:  ldx #2
+            loop:   jsr outer
+                    dex
+                    bne loop
+            done:   jmp done
+            outer:  jsr inner
+                    lda #>(back-1)  ; jumps to back without leaving outer
+                    pha
+                    lda #<(back-1)
+                    pha
+                    rts
+            back:   rts
+            inner:  nop
+                    rts
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut profiler = Profiler::new();
+        profiler.add_label(0x0616, "inner");
+        let mut max_depth = 0;
+        while cpu.get_PC() != 0x0608{
+            cpu.run_single_traced(&mut mem, &mut profiler).unwrap();
+            max_depth = max_depth.max(profiler.call_stack().depth());
+            if cpu.get_PC() == 0x0615{
+                assert_eq!(profiler.call_stack().frames()[0].function, 0x060b); //back in outer after the RTS jump
+                assert_eq!(profiler.call_stack().depth(), 1);
+            }
+        }
+        assert_eq!(max_depth, 2);
+        assert_eq!(profiler.call_stack().depth(), 0);
+        assert_eq!(profiler.instructions(), 1 + 2*12);
+        assert_eq!(profiler.cycles(), cpu.cycles());
+        assert_eq!(profiler.pc(0x0616), PcProfile { instructions: 2, cycles: 4 });
+        assert_eq!(profiler.opcode_histogram()[0x60], 6); //RTS
+        assert_eq!(profiler.hot_spots(1)[0].0, 0x0602);
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "root 23\nroot;060B 56\nroot;060B;inner 16\n");
+        let mut report = Vec::new();
+        profiler.write_report(&mut report, 3).unwrap();
+        assert!(String::from_utf8(report).unwrap().contains("0602  JSR $060b"));
+    }
+
+    #[test]
+    fn test_backtrace(){
+        let mut mem = Memory::new(64*1024);
+        let code = assemble("
+            start:  jsr outer
+            done:   jmp done
+            outer:  jsr inner
+                    rts
+            inner:  nop
+                    nop
+                    rts
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+        mem.write_memory(0x0700, 0xea); //NMI handler: NOP, RTI
+        mem.write_memory(0x0701, 0x40);
+        mem.write_memory(0xfffa, 0x00);
+        mem.write_memory(0xfffb, 0x07);
+        let symbols = Symbols::parse("al C:0606 .outer\nal C:060a .inner\nnmi = $0700\nstart = $0600\n; comment\n");
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.describe(0x060b), "inner+1");
+        assert_eq!(symbols.describe(0x0800), "$0800");
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.run_single(&mut mem).unwrap();
+        cpu.run_single(&mut mem).unwrap();
+        cpu.interrupt(InterruptType::NMI, &mut mem);
+        let frames = cpu.call_stack().frames();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1], Frame { kind: FrameKind::Jsr, function: 0x060a, call_site: 0x0606, return_address: 0x0609, sp: 0xfb });
+        assert_eq!(frames[2], Frame { kind: FrameKind::Nmi, function: 0x0700, call_site: 0x060a, return_address: 0x060a, sp: 0xf8 });
+
+        let mut backtrace = Vec::new();
+        cpu.call_stack().write_backtrace(&mut backtrace, cpu.get_PC(), &symbols).unwrap();
+        assert_eq!(String::from_utf8(backtrace).unwrap(), "\
+#0      0700 nmi
+#1  NMI 0700 nmi              from 060A inner            to 060A inner
+#2  JSR 060A inner            from 0606 outer            to 0609 outer+3
+#3  JSR 0606 outer            from 0600 start            to 0603 start+3
+");
+
+        cpu.run_single(&mut mem).unwrap();
+        cpu.run_single(&mut mem).unwrap(); //RTI
+        assert_eq!(cpu.call_stack().depth(), 2);
+        while cpu.get_PC() != 0x0603{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.call_stack().depth(), 0);
+
+        cpu.reset_at(0x0600);
+        cpu.run_single(&mut mem).unwrap();
+        assert_eq!(cpu.call_stack().depth(), 1);
+        cpu.reset_at(0x0600);
+        assert_eq!(cpu.call_stack().depth(), 0);
+    }
+
+    #[test]
+    fn test_coverage(){
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+                    ldx #0
+            loop:   lda $0300,x
+                    sta $0400,x
+                    inc $0500
+                    inx
+                    cpx #2
+                    bne loop
+            done:   jmp done
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let mut coverage = Coverage::new();
+        while cpu.get_PC() != 0x0610{
+            cpu.run_single_traced(&mut mem, &mut coverage).unwrap();
+        }
+        assert_eq!(coverage.get(Bank::Ram, 0x0600), OPCODE);
+        assert_eq!(coverage.get(Bank::Ram, 0x0601), OPERAND);
+        assert_eq!(coverage.get(Bank::Ram, 0x0301), READ);
+        assert_eq!(coverage.get(Bank::Ram, 0x0302), 0);
+        assert_eq!(coverage.get(Bank::Ram, 0x0401), WRITE);
+        assert_eq!(coverage.get(Bank::Ram, 0x0500), READ | WRITE);
+        assert_eq!(coverage.get(Bank::Rom, 0x0600), 0);
+        assert_eq!(coverage.ranges(Bank::Ram, OPCODE | OPERAND), vec![0x0600..=0x060f]);
+        assert_eq!(coverage.summary(Bank::Ram, 0x0600..=0x0615).opcodes, 7);
+
+        let mut bitmap = Vec::new();
+        coverage.write_bitmap(&mut bitmap).unwrap();
+        assert_eq!(bitmap.len(), BITMAP_SIZE);
+        let mut other = Coverage::new();
+        other.mark(Bank::Ram, 0x0610, OPCODE);
+        other.merge(&Coverage::read_bitmap(&mut bitmap.as_slice()).unwrap());
+        assert_eq!(other.ranges(Bank::Ram, OPCODE | OPERAND), vec![0x0600..=0x0610]);
+        assert!(Coverage::read_bitmap(&mut &bitmap[1..]).is_err());
+
+        let mut report = Vec::new();
+        coverage.write_report(&mut report).unwrap();
+        assert!(String::from_utf8(report).unwrap().contains("RAM  0600-060F      16       7       9       0       0"));
+
+        // Stores outside the load/store operations come from the bus
+        let code = assemble("ldx #$ff\nldy #1\nshx $0410,y\npha", 0x0700, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0700 + i as u16, *b);
+        }
+        cpu.reset_at(0x0700);
+        let stack = 0x0100 + cpu.get_SP() as u16;
+        let mut coverage = Coverage::new();
+        for _i in 0..4{
+            cpu.run_single_traced(&mut mem, &mut coverage).unwrap();
+        }
+        assert_eq!(mem.read_memory(0x0411), 0x05);
+        assert_eq!(coverage.get(Bank::Ram, 0x0411), WRITE);
+        assert_eq!(coverage.get(Bank::Ram, stack), WRITE);
+        assert_eq!(coverage.summary(Bank::Ram, 0x0000..=0x06ff).writes, 2);
+    }
+
+    /// ROM at $e000 while bit 0 of the port at $0001 is set, like the C64 KERNAL.
+    struct BankedRom{
+        memory: Memory,
+        rom: bool,
+    }
+
+    impl Memory6502 for BankedRom{
+        fn write_memory(&mut self, address: u16, value: u8){
+            if address == 0x0001{
+                self.rom = value & 0x01 != 0;
+            }
+            self.memory.write_memory(address, value);
+        }
+
+        fn read_memory(&mut self, address: u16) -> u8{
+            self.memory.read_memory(address)
+        }
+
+        fn read_memory_word(&mut self, address: u16) -> u16{
+            self.memory.read_memory_word(address)
+        }
+
+        fn peek(&self, address: u16) -> u8{
+            self.memory.peek(address)
+        }
+
+        fn poke(&mut self, address: u16, value: u8){
+            self.memory.poke(address, value)
+        }
+
+        fn bank(&self, address: u16) -> Bank{
+            if self.rom && address >= 0xe000 { Bank::Rom } else { Bank::Ram }
+        }
+    }
+
+    #[test]
+    fn test_coverage_bank_switch(){
+        let mut mem = BankedRom { memory: Memory::new(64*1024), rom: true };
+        let code = assemble("lda #0\nsta $01\nlda $e000", 0xe000, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.memory.write_memory(0xe000 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0xe000);
+        let mut coverage = Coverage::new();
+        for _i in 0..3{
+            cpu.run_single_traced(&mut mem, &mut coverage).unwrap();
+        }
+        // The store banking the ROM out was fetched from the ROM, the load after it reads the RAM
+        assert!(!mem.rom);
+        assert_eq!(coverage.get(Bank::Rom, 0xe002), OPCODE);
+        assert_eq!(coverage.get(Bank::Rom, 0xe003), OPERAND);
+        assert_eq!(coverage.get(Bank::Ram, 0xe002), 0);
+        assert_eq!(coverage.get(Bank::Ram, 0xe004), OPCODE);
+        assert_eq!(coverage.get(Bank::Ram, 0xe000), READ);
+        assert_eq!(coverage.get(Bank::Rom, 0xe000), OPCODE);
+    }
+
+    #[test]
+    fn test_breakpoints(){
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+                    ldx #0
+            loop:   inx
+                    stx $0200
+                    lda $0300
+                    cpx #3
+                    bne loop
+            done:   jmp done
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+        mem.write_memory(0x0300, 0x20);
+
+        let condition = Condition::parse("A==$20 && ($0300>$80 || X!=0)").unwrap();
+        assert_eq!(condition.to_string(), "A==$20 && ($0300>$80 || X!=0)");
+        assert!(!condition.eval(&CpuRegisters { A: 0x20, X: 0, Y: 0, SP: 0xff, PC: 0, P: StatusRegister::new(0) }, &mem));
+        assert!(condition.eval(&CpuRegisters { A: 0x20, X: 1, Y: 0, SP: 0xff, PC: 0, P: StatusRegister::new(0) }, &mem));
+        assert!(Condition::parse("A==").is_err());
+        assert!(Condition::parse("Q==1").is_err());
+        assert!(Condition::parse("(A==1").is_err());
+        assert!(Condition::parse("A\u{a0}== 1").is_ok());
+        assert_eq!(Breakpoint::parse("once write $0200-$02ff if C==1").unwrap(),
+            Breakpoint::watch(WatchKind::Write, 0x0200..=0x02ff).with_condition(Condition::parse("C==1").unwrap()).temporary());
+        assert!(Breakpoint::parse("jump $0600").is_err());
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        let every = cpu.breakpoints_mut().add(Breakpoint::at(0x0602));
+        let conditional = cpu.breakpoints_mut().add(Breakpoint::parse("$0606 if X==2").unwrap());
+        let once = cpu.breakpoints_mut().add(Breakpoint::at(0x0609).temporary());
+        let mut stops = Vec::new();
+        while cpu.get_PC() != 0x060d{
+            if let Err(error) = cpu.run_single(&mut mem){
+                stops.push((error, cpu.breakpoints().last_hit().unwrap()));
+            }
+        }
+        assert_eq!(stops, vec![
+            (CpuError::Breakpoint { pc: 0x0602 }, every), (CpuError::Breakpoint { pc: 0x0609 }, once),
+            (CpuError::Breakpoint { pc: 0x0602 }, every), (CpuError::Breakpoint { pc: 0x0606 }, conditional),
+            (CpuError::Breakpoint { pc: 0x0602 }, every)]);
+        assert_eq!(cpu.get_X(), 3);
+        assert_eq!(cpu.breakpoints().get(every).unwrap().hits, 3);
+        assert!(cpu.breakpoints().get(once).is_none());
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.breakpoints_mut().add(Breakpoint::parse("write $0200 if $0200==2").unwrap());
+        cpu.breakpoints_mut().add(Breakpoint::parse("read $0300-$0303").unwrap());
+        let code = cpu.breakpoints_mut().add(Breakpoint::parse("access $0602-$060c").unwrap()); //fetches do not count
+        let mut stops = Vec::new();
+        while cpu.get_PC() != 0x060d{
+            if let Err(error) = cpu.run_single(&mut mem){
+                stops.push(error);
+            }
+        }
+        assert_eq!(stops.len(), 4);
+        assert_eq!(stops[0], CpuError::Watchpoint { pc: 0x0606, address: 0x0300, value: 0x20, write: false });
+        assert_eq!(stops[1], CpuError::Watchpoint { pc: 0x0603, address: 0x0200, value: 0x02, write: true });
+        assert_eq!(cpu.breakpoints().get(code).unwrap().hits, 0);
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        cpu.breakpoints_mut().add(Breakpoint::at(0x0600));
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Breakpoint { pc: 0x0600 }));
+        cpu.breakpoints_mut().clear();
+        cpu.breakpoints_mut().add(Breakpoint::at(0x0600));
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Breakpoint { pc: 0x0600 })); //not let through after a clear
+    }
+
+    #[test]
+    fn test_watchpoint_dummy_cycles(){
+        let mut mem = Memory::new(64*1024);
+        let code = assemble("
+                    ldx #1
+                    lda $10,x
+                    lda $10ff,x
+                    inc $10
+            done:   jmp done
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+        mem.write_memory(0x1100, 0x22);
+        let mut run = |watches: &[&str]|{
+            mem.write_memory(0x0010, 0x05);
+            let mut cpu = CPU6502::new();
+            cpu.reset_at(0x0600);
+            cpu.enable_trace(8);
+            for watch in watches{
+                cpu.breakpoints_mut().add(Breakpoint::parse(watch).unwrap());
+            }
+            let mut stops = Vec::new();
+            while cpu.get_PC() != 0x0609{
+                if let Err(error) = cpu.run_single(&mut mem){
+                    stops.push(error);
+                }
+            }
+            (stops, cpu.get_last_state())
+        };
+
+        // The ZP,X read of the unindexed $10 and the page crossing read of $1000 are dummy cycles
+        let (stops, _) = run(&["read $0010", "read $1000", "read $1100"]);
+        assert_eq!(stops, vec![
+            CpuError::Watchpoint { pc: 0x0604, address: 0x1100, value: 0x22, write: false },
+            CpuError::Watchpoint { pc: 0x0607, address: 0x0010, value: 0x05, write: false }]);
+
+        // INC writes the old value back first, only the result is reported
+        let (stops, inc) = run(&["write $0010"]);
+        assert_eq!(stops, vec![CpuError::Watchpoint { pc: 0x0607, address: 0x0010, value: 0x06, write: true }]);
+        let kinds: Vec<(u16, u8, bool, CycleKind)> = inc.bus_cycles().iter().map(|c| (c.address, c.value, c.write, c.kind)).collect();
+        assert_eq!(kinds, vec![(0x0607, 0xe6, false, CycleKind::Fetch), (0x0608, 0x10, false, CycleKind::Fetch),
+            (0x0010, 0x05, false, CycleKind::Data), (0x0010, 0x05, true, CycleKind::Dummy), (0x0010, 0x06, true, CycleKind::Data)]);
+    }
+
+    #[test]
+    fn test_run_command(){
+        let mut mem = Memory::new(4*1024);
+        let code = assemble("
+                    jsr sub
+                    jsr sub
+                    nop
+            done:   jmp done
+            sub:    jsr inner
+                    inx
+                    rts
+            inner:  iny
+                    rts
+        ", 0x0600, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0x0600 + i as u16, *b);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0x0600);
+        assert_eq!(cpu.run_command(&mut mem, RunCommand::StepOver, &mut ()), Ok(0x060e));
+        assert_eq!((cpu.get_PC(), cpu.get_X(), cpu.get_Y()), (0x0603, 1, 1));
+        cpu.run_command(&mut mem, RunCommand::Instructions(2), &mut ()).unwrap();
+        assert_eq!(cpu.get_PC(), 0x060f);
+        cpu.run_command(&mut mem, RunCommand::StepOut, &mut ()).unwrap();
+        assert_eq!((cpu.get_PC(), cpu.get_Y()), (0x060d, 2));
+        cpu.run_command(&mut mem, RunCommand::StepOut, &mut ()).unwrap();
+        assert_eq!((cpu.get_PC(), cpu.get_X()), (0x0606, 2));
+        cpu.run_command(&mut mem, RunCommand::StepOver, &mut ()).unwrap();
+        assert_eq!(cpu.get_PC(), 0x0607);
+        cpu.run_command(&mut mem, RunCommand::Address(0x0607), &mut ()).unwrap();
+        assert_eq!(cpu.get_PC(), 0x0607);
+        let cycles = cpu.cycles();
+        cpu.run_command(&mut mem, RunCommand::Cycles(10), &mut ()).unwrap();
+        assert_eq!(cpu.cycles() - cycles, 12);
+
+        // Breakpoints still stop a command
+        cpu.reset_at(0x0600);
+        cpu.breakpoints_mut().add(Breakpoint::at(0x060f));
+        assert_eq!(cpu.run_command(&mut mem, RunCommand::StepOver, &mut ()), Err(CpuError::Breakpoint { pc: 0x060f }));
+    }
+
+    #[test]
+    fn test_halt_policy(){
+        let mut mem = Memory::new(64*1024);
+        let code = assemble("
+            loop: jmp loop
+        ", 0xfffd, CpuVariant::Nmos6502).unwrap();
+        for (i, b) in code.iter().enumerate(){
+            mem.write_memory(0xfffd + i as u16, *b);
+        }
+        mem.write_memory(0x0000, 0xea); //NOP after PC wraps
+
+        let mut cpu = CPU6502::new();
+        cpu.reset_at(0xfffd);
+        for _i in 0..3{
+            assert_eq!(cpu.run_single(&mut mem).unwrap(), 0xfffd);
+        }
+
+        cpu.set_halt_policy(HaltPolicy::HaltOnSelfLoop);
+        assert_eq!(cpu.run_single(&mut mem), Err(CpuError::Halted { pc: 0xfffd, reason: HaltReason::SelfLoop }));
+
+        mem.write_memory(0xfffd, 0xea); //NOP
+        mem.write_memory(0xfffe, 0xea); //NOP
+        mem.write_memory(0xffff, 0xea); //NOP
+        for _i in 0..4{
+            cpu.run_single(&mut mem).unwrap();
+        }
+        assert_eq!(cpu.PC, 0x0001);
+    }
+}
@@ -0,0 +1,835 @@
+use super::memory::Memory6502;
+use super::{Access, AdressingType, CpuVariant, InterruptType, CPU6502};
+use self::AdressingType::*;
+use self::Operation::*;
+
+/// What an opcode does with its operand. `run_single` resolves the addressing mode and
+/// performs the bus accesses around it, so one operation serves every mode of an instruction.
+#[derive(Clone,Copy)]
+pub enum Operation{
+    /// Gets the value read from the operand.
+    Read(fn(&mut CPU6502, u8)),
+    /// Returns the value to store.
+    Write(fn(&mut CPU6502) -> u8),
+    /// Gets the old value from memory or the accumulator and returns the new one.
+    Modify(fn(&mut CPU6502, u8) -> u8),
+    /// Works on registers only.
+    Register(fn(&mut CPU6502)),
+    /// Decides if the branch is taken, BBR/BBS get the tested zero page value.
+    Branch(fn(&CPU6502, u8) -> bool),
+    /// Jumps, stack operations and the unstable stores drive the bus themselves.
+    /// Returns the address shown in the trace.
+    Control(fn(&mut CPU6502, AdressingType, &mut dyn Memory6502) -> u16),
+}
+
+/// One entry of the opcode table, everything the decoder, the trace and the cycle counting know about an opcode.
+#[derive(Clone,Copy)]
+pub struct Opcode{
+    pub mnemonic: &'static str,
+    pub mode: AdressingType,
+    /// Base cycle count, taken branches add their own cycles.
+    pub cycles: u8,
+    /// Pays one more cycle when indexing crosses a page.
+    pub page_penalty: bool,
+    /// One of the NMOS opcodes outside the documented instruction set.
+    pub undocumented: bool,
+    pub operation: Operation,
+}
+
+impl Opcode{
+    const fn with_page_penalty(self) -> Self{
+        Opcode { page_penalty: true, ..self }
+    }
+
+    const fn undocumented(self) -> Self{
+        Opcode { undocumented: true, ..self }
+    }
+}
+
+/// Indexed reads pay the page crossing cycle, stores and read-modify-writes always spend it.
+const fn op(mnemonic: &'static str, mode: AdressingType, cycles: u8, operation: Operation) -> Opcode{
+    let page_penalty = matches!(operation, Read(_)) && matches!(mode, AbsoluteX | AbsoluteY | IndirectY);
+    Opcode { mnemonic, mode, cycles, page_penalty, undocumented: false, operation }
+}
+
+pub fn opcode_table(variant: CpuVariant) -> &'static [Opcode; 256]{
+    match variant {
+        CpuVariant::Nmos6502 => &NMOS_OPCODES,
+        CpuVariant::Cmos65C02 => &CMOS_OPCODES,
+    }
+}
+
+static NMOS_OPCODES: [Opcode; 256] = NMOS;
+static CMOS_OPCODES: [Opcode; 256] = cmos();
+
+const NMOS: [Opcode; 256] = [
+    op("BRK", Implied, 7, Control(CPU6502::brk)), // 0x00
+    op("ORA", IndirectX, 6, Read(CPU6502::ora)), // 0x01
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x02
+    op("SLO", IndirectX, 8, Modify(CPU6502::slo)).undocumented(), // 0x03
+    op("NOP", ZeroPage, 3, Read(CPU6502::nop_read)).undocumented(), // 0x04
+    op("ORA", ZeroPage, 3, Read(CPU6502::ora)), // 0x05
+    op("ASL", ZeroPage, 5, Modify(CPU6502::asl)), // 0x06
+    op("SLO", ZeroPage, 5, Modify(CPU6502::slo)).undocumented(), // 0x07
+    op("PHP", Implied, 3, Control(CPU6502::php)), // 0x08
+    op("ORA", Immediate, 2, Read(CPU6502::ora)), // 0x09
+    op("ASL", Accumulator, 2, Modify(CPU6502::asl)), // 0x0a
+    op("ANC", Immediate, 2, Read(CPU6502::anc)).undocumented(), // 0x0b
+    op("NOP", Absolute, 4, Read(CPU6502::nop_read)).undocumented(), // 0x0c
+    op("ORA", Absolute, 4, Read(CPU6502::ora)), // 0x0d
+    op("ASL", Absolute, 6, Modify(CPU6502::asl)), // 0x0e
+    op("SLO", Absolute, 6, Modify(CPU6502::slo)).undocumented(), // 0x0f
+    op("BPL", Relative, 2, Branch(CPU6502::bpl)), // 0x10
+    op("ORA", IndirectY, 5, Read(CPU6502::ora)), // 0x11
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x12
+    op("SLO", IndirectY, 8, Modify(CPU6502::slo)).undocumented(), // 0x13
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x14
+    op("ORA", ZeroPageX, 4, Read(CPU6502::ora)), // 0x15
+    op("ASL", ZeroPageX, 6, Modify(CPU6502::asl)), // 0x16
+    op("SLO", ZeroPageX, 6, Modify(CPU6502::slo)).undocumented(), // 0x17
+    op("CLC", Implied, 2, Register(CPU6502::clc)), // 0x18
+    op("ORA", AbsoluteY, 4, Read(CPU6502::ora)), // 0x19
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0x1a
+    op("SLO", AbsoluteY, 7, Modify(CPU6502::slo)).undocumented(), // 0x1b
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x1c
+    op("ORA", AbsoluteX, 4, Read(CPU6502::ora)), // 0x1d
+    op("ASL", AbsoluteX, 7, Modify(CPU6502::asl)), // 0x1e
+    op("SLO", AbsoluteX, 7, Modify(CPU6502::slo)).undocumented(), // 0x1f
+    op("JSR", Absolute, 6, Control(CPU6502::jsr)), // 0x20
+    op("AND", IndirectX, 6, Read(CPU6502::and)), // 0x21
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x22
+    op("RLA", IndirectX, 8, Modify(CPU6502::rla)).undocumented(), // 0x23
+    op("BIT", ZeroPage, 3, Read(CPU6502::bit)), // 0x24
+    op("AND", ZeroPage, 3, Read(CPU6502::and)), // 0x25
+    op("ROL", ZeroPage, 5, Modify(CPU6502::rol)), // 0x26
+    op("RLA", ZeroPage, 5, Modify(CPU6502::rla)).undocumented(), // 0x27
+    op("PLP", Implied, 4, Control(CPU6502::plp)), // 0x28
+    op("AND", Immediate, 2, Read(CPU6502::and)), // 0x29
+    op("ROL", Accumulator, 2, Modify(CPU6502::rol)), // 0x2a
+    op("ANC", Immediate, 2, Read(CPU6502::anc)).undocumented(), // 0x2b
+    op("BIT", Absolute, 4, Read(CPU6502::bit)), // 0x2c
+    op("AND", Absolute, 4, Read(CPU6502::and)), // 0x2d
+    op("ROL", Absolute, 6, Modify(CPU6502::rol)), // 0x2e
+    op("RLA", Absolute, 6, Modify(CPU6502::rla)).undocumented(), // 0x2f
+    op("BMI", Relative, 2, Branch(CPU6502::bmi)), // 0x30
+    op("AND", IndirectY, 5, Read(CPU6502::and)), // 0x31
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x32
+    op("RLA", IndirectY, 8, Modify(CPU6502::rla)).undocumented(), // 0x33
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x34
+    op("AND", ZeroPageX, 4, Read(CPU6502::and)), // 0x35
+    op("ROL", ZeroPageX, 6, Modify(CPU6502::rol)), // 0x36
+    op("RLA", ZeroPageX, 6, Modify(CPU6502::rla)).undocumented(), // 0x37
+    op("SEC", Implied, 2, Register(CPU6502::sec)), // 0x38
+    op("AND", AbsoluteY, 4, Read(CPU6502::and)), // 0x39
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0x3a
+    op("RLA", AbsoluteY, 7, Modify(CPU6502::rla)).undocumented(), // 0x3b
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x3c
+    op("AND", AbsoluteX, 4, Read(CPU6502::and)), // 0x3d
+    op("ROL", AbsoluteX, 7, Modify(CPU6502::rol)), // 0x3e
+    op("RLA", AbsoluteX, 7, Modify(CPU6502::rla)).undocumented(), // 0x3f
+    op("RTI", Implied, 6, Control(CPU6502::rti)), // 0x40
+    op("EOR", IndirectX, 6, Read(CPU6502::eor)), // 0x41
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x42
+    op("SRE", IndirectX, 8, Modify(CPU6502::sre)).undocumented(), // 0x43
+    op("NOP", ZeroPage, 3, Read(CPU6502::nop_read)).undocumented(), // 0x44
+    op("EOR", ZeroPage, 3, Read(CPU6502::eor)), // 0x45
+    op("LSR", ZeroPage, 5, Modify(CPU6502::lsr)), // 0x46
+    op("SRE", ZeroPage, 5, Modify(CPU6502::sre)).undocumented(), // 0x47
+    op("PHA", Implied, 3, Control(CPU6502::pha)), // 0x48
+    op("EOR", Immediate, 2, Read(CPU6502::eor)), // 0x49
+    op("LSR", Accumulator, 2, Modify(CPU6502::lsr)), // 0x4a
+    op("ALR", Immediate, 2, Read(CPU6502::alr)).undocumented(), // 0x4b
+    op("JMP", Absolute, 3, Control(CPU6502::jmp)), // 0x4c
+    op("EOR", Absolute, 4, Read(CPU6502::eor)), // 0x4d
+    op("LSR", Absolute, 6, Modify(CPU6502::lsr)), // 0x4e
+    op("SRE", Absolute, 6, Modify(CPU6502::sre)).undocumented(), // 0x4f
+    op("BVC", Relative, 2, Branch(CPU6502::bvc)), // 0x50
+    op("EOR", IndirectY, 5, Read(CPU6502::eor)), // 0x51
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x52
+    op("SRE", IndirectY, 8, Modify(CPU6502::sre)).undocumented(), // 0x53
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x54
+    op("EOR", ZeroPageX, 4, Read(CPU6502::eor)), // 0x55
+    op("LSR", ZeroPageX, 6, Modify(CPU6502::lsr)), // 0x56
+    op("SRE", ZeroPageX, 6, Modify(CPU6502::sre)).undocumented(), // 0x57
+    op("CLI", Implied, 2, Register(CPU6502::cli)), // 0x58
+    op("EOR", AbsoluteY, 4, Read(CPU6502::eor)), // 0x59
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0x5a
+    op("SRE", AbsoluteY, 7, Modify(CPU6502::sre)).undocumented(), // 0x5b
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x5c
+    op("EOR", AbsoluteX, 4, Read(CPU6502::eor)), // 0x5d
+    op("LSR", AbsoluteX, 7, Modify(CPU6502::lsr)), // 0x5e
+    op("SRE", AbsoluteX, 7, Modify(CPU6502::sre)).undocumented(), // 0x5f
+    op("RTS", Implied, 6, Control(CPU6502::rts)), // 0x60
+    op("ADC", IndirectX, 6, Read(CPU6502::adc)), // 0x61
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x62
+    op("RRA", IndirectX, 8, Modify(CPU6502::rra)).undocumented(), // 0x63
+    op("NOP", ZeroPage, 3, Read(CPU6502::nop_read)).undocumented(), // 0x64
+    op("ADC", ZeroPage, 3, Read(CPU6502::adc)), // 0x65
+    op("ROR", ZeroPage, 5, Modify(CPU6502::ror)), // 0x66
+    op("RRA", ZeroPage, 5, Modify(CPU6502::rra)).undocumented(), // 0x67
+    op("PLA", Implied, 4, Control(CPU6502::pla)), // 0x68
+    op("ADC", Immediate, 2, Read(CPU6502::adc)), // 0x69
+    op("ROR", Accumulator, 2, Modify(CPU6502::ror)), // 0x6a
+    op("ARR", Immediate, 2, Read(CPU6502::arr)).undocumented(), // 0x6b
+    op("JMP", Indirect, 5, Control(CPU6502::jmp)), // 0x6c
+    op("ADC", Absolute, 4, Read(CPU6502::adc)), // 0x6d
+    op("ROR", Absolute, 6, Modify(CPU6502::ror)), // 0x6e
+    op("RRA", Absolute, 6, Modify(CPU6502::rra)).undocumented(), // 0x6f
+    op("BVS", Relative, 2, Branch(CPU6502::bvs)), // 0x70
+    op("ADC", IndirectY, 5, Read(CPU6502::adc)), // 0x71
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x72
+    op("RRA", IndirectY, 8, Modify(CPU6502::rra)).undocumented(), // 0x73
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x74
+    op("ADC", ZeroPageX, 4, Read(CPU6502::adc)), // 0x75
+    op("ROR", ZeroPageX, 6, Modify(CPU6502::ror)), // 0x76
+    op("RRA", ZeroPageX, 6, Modify(CPU6502::rra)).undocumented(), // 0x77
+    op("SEI", Implied, 2, Register(CPU6502::sei)), // 0x78
+    op("ADC", AbsoluteY, 4, Read(CPU6502::adc)), // 0x79
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0x7a
+    op("RRA", AbsoluteY, 7, Modify(CPU6502::rra)).undocumented(), // 0x7b
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0x7c
+    op("ADC", AbsoluteX, 4, Read(CPU6502::adc)), // 0x7d
+    op("ROR", AbsoluteX, 7, Modify(CPU6502::ror)), // 0x7e
+    op("RRA", AbsoluteX, 7, Modify(CPU6502::rra)).undocumented(), // 0x7f
+    op("NOP", Immediate, 2, Read(CPU6502::nop_read)).undocumented(), // 0x80
+    op("STA", IndirectX, 6, Write(CPU6502::sta)), // 0x81
+    op("NOP", Immediate, 2, Read(CPU6502::nop_read)).undocumented(), // 0x82
+    op("SAX", IndirectX, 6, Write(CPU6502::sax)).undocumented(), // 0x83
+    op("STY", ZeroPage, 3, Write(CPU6502::sty)), // 0x84
+    op("STA", ZeroPage, 3, Write(CPU6502::sta)), // 0x85
+    op("STX", ZeroPage, 3, Write(CPU6502::stx)), // 0x86
+    op("SAX", ZeroPage, 3, Write(CPU6502::sax)).undocumented(), // 0x87
+    op("DEY", Implied, 2, Register(CPU6502::dey)), // 0x88
+    op("NOP", Immediate, 2, Read(CPU6502::nop_read)).undocumented(), // 0x89
+    op("TXA", Implied, 2, Register(CPU6502::txa)), // 0x8a
+    op("ANE", Immediate, 2, Read(CPU6502::ane)).undocumented(), // 0x8b
+    op("STY", Absolute, 4, Write(CPU6502::sty)), // 0x8c
+    op("STA", Absolute, 4, Write(CPU6502::sta)), // 0x8d
+    op("STX", Absolute, 4, Write(CPU6502::stx)), // 0x8e
+    op("SAX", Absolute, 4, Write(CPU6502::sax)).undocumented(), // 0x8f
+    op("BCC", Relative, 2, Branch(CPU6502::bcc)), // 0x90
+    op("STA", IndirectY, 6, Write(CPU6502::sta)), // 0x91
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0x92
+    op("SHA", IndirectY, 6, Control(CPU6502::sha)).undocumented(), // 0x93
+    op("STY", ZeroPageX, 4, Write(CPU6502::sty)), // 0x94
+    op("STA", ZeroPageX, 4, Write(CPU6502::sta)), // 0x95
+    op("STX", ZeroPageY, 4, Write(CPU6502::stx)), // 0x96
+    op("SAX", ZeroPageY, 4, Write(CPU6502::sax)).undocumented(), // 0x97
+    op("TYA", Implied, 2, Register(CPU6502::tya)), // 0x98
+    op("STA", AbsoluteY, 5, Write(CPU6502::sta)), // 0x99
+    op("TXS", Implied, 2, Register(CPU6502::txs)), // 0x9a
+    op("TAS", AbsoluteY, 5, Control(CPU6502::tas)).undocumented(), // 0x9b
+    op("SHY", AbsoluteX, 5, Control(CPU6502::shy)).undocumented(), // 0x9c
+    op("STA", AbsoluteX, 5, Write(CPU6502::sta)), // 0x9d
+    op("SHX", AbsoluteY, 5, Control(CPU6502::shx)).undocumented(), // 0x9e
+    op("SHA", AbsoluteY, 5, Control(CPU6502::sha)).undocumented(), // 0x9f
+    op("LDY", Immediate, 2, Read(CPU6502::ldy)), // 0xa0
+    op("LDA", IndirectX, 6, Read(CPU6502::lda)), // 0xa1
+    op("LDX", Immediate, 2, Read(CPU6502::ldx)), // 0xa2
+    op("LAX", IndirectX, 6, Read(CPU6502::lax)).undocumented(), // 0xa3
+    op("LDY", ZeroPage, 3, Read(CPU6502::ldy)), // 0xa4
+    op("LDA", ZeroPage, 3, Read(CPU6502::lda)), // 0xa5
+    op("LDX", ZeroPage, 3, Read(CPU6502::ldx)), // 0xa6
+    op("LAX", ZeroPage, 3, Read(CPU6502::lax)).undocumented(), // 0xa7
+    op("TAY", Implied, 2, Register(CPU6502::tay)), // 0xa8
+    op("LDA", Immediate, 2, Read(CPU6502::lda)), // 0xa9
+    op("TAX", Implied, 2, Register(CPU6502::tax)), // 0xaa
+    op("LXA", Immediate, 2, Read(CPU6502::lxa)).undocumented(), // 0xab
+    op("LDY", Absolute, 4, Read(CPU6502::ldy)), // 0xac
+    op("LDA", Absolute, 4, Read(CPU6502::lda)), // 0xad
+    op("LDX", Absolute, 4, Read(CPU6502::ldx)), // 0xae
+    op("LAX", Absolute, 4, Read(CPU6502::lax)).undocumented(), // 0xaf
+    op("BCS", Relative, 2, Branch(CPU6502::bcs)), // 0xb0
+    op("LDA", IndirectY, 5, Read(CPU6502::lda)), // 0xb1
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0xb2
+    op("LAX", IndirectY, 5, Read(CPU6502::lax)).undocumented(), // 0xb3
+    op("LDY", ZeroPageX, 4, Read(CPU6502::ldy)), // 0xb4
+    op("LDA", ZeroPageX, 4, Read(CPU6502::lda)), // 0xb5
+    op("LDX", ZeroPageY, 4, Read(CPU6502::ldx)), // 0xb6
+    op("LAX", ZeroPageY, 4, Read(CPU6502::lax)).undocumented(), // 0xb7
+    op("CLV", Implied, 2, Register(CPU6502::clv)), // 0xb8
+    op("LDA", AbsoluteY, 4, Read(CPU6502::lda)), // 0xb9
+    op("TSX", Implied, 2, Register(CPU6502::tsx)), // 0xba
+    op("LAS", AbsoluteY, 4, Read(CPU6502::las)).undocumented(), // 0xbb
+    op("LDY", AbsoluteX, 4, Read(CPU6502::ldy)), // 0xbc
+    op("LDA", AbsoluteX, 4, Read(CPU6502::lda)), // 0xbd
+    op("LDX", AbsoluteY, 4, Read(CPU6502::ldx)), // 0xbe
+    op("LAX", AbsoluteY, 4, Read(CPU6502::lax)).undocumented(), // 0xbf
+    op("CPY", Immediate, 2, Read(CPU6502::cpy)), // 0xc0
+    op("CMP", IndirectX, 6, Read(CPU6502::cmp)), // 0xc1
+    op("NOP", Immediate, 2, Read(CPU6502::nop_read)).undocumented(), // 0xc2
+    op("DCP", IndirectX, 8, Modify(CPU6502::dcp)).undocumented(), // 0xc3
+    op("CPY", ZeroPage, 3, Read(CPU6502::cpy)), // 0xc4
+    op("CMP", ZeroPage, 3, Read(CPU6502::cmp)), // 0xc5
+    op("DEC", ZeroPage, 5, Modify(CPU6502::dec)), // 0xc6
+    op("DCP", ZeroPage, 5, Modify(CPU6502::dcp)).undocumented(), // 0xc7
+    op("INY", Implied, 2, Register(CPU6502::iny)), // 0xc8
+    op("CMP", Immediate, 2, Read(CPU6502::cmp)), // 0xc9
+    op("DEX", Implied, 2, Register(CPU6502::dex)), // 0xca
+    op("SBX", Immediate, 2, Read(CPU6502::sbx)).undocumented(), // 0xcb
+    op("CPY", Absolute, 4, Read(CPU6502::cpy)), // 0xcc
+    op("CMP", Absolute, 4, Read(CPU6502::cmp)), // 0xcd
+    op("DEC", Absolute, 6, Modify(CPU6502::dec)), // 0xce
+    op("DCP", Absolute, 6, Modify(CPU6502::dcp)).undocumented(), // 0xcf
+    op("BNE", Relative, 2, Branch(CPU6502::bne)), // 0xd0
+    op("CMP", IndirectY, 5, Read(CPU6502::cmp)), // 0xd1
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0xd2
+    op("DCP", IndirectY, 8, Modify(CPU6502::dcp)).undocumented(), // 0xd3
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0xd4
+    op("CMP", ZeroPageX, 4, Read(CPU6502::cmp)), // 0xd5
+    op("DEC", ZeroPageX, 6, Modify(CPU6502::dec)), // 0xd6
+    op("DCP", ZeroPageX, 6, Modify(CPU6502::dcp)).undocumented(), // 0xd7
+    op("CLD", Implied, 2, Register(CPU6502::cld)), // 0xd8
+    op("CMP", AbsoluteY, 4, Read(CPU6502::cmp)), // 0xd9
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0xda
+    op("DCP", AbsoluteY, 7, Modify(CPU6502::dcp)).undocumented(), // 0xdb
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0xdc
+    op("CMP", AbsoluteX, 4, Read(CPU6502::cmp)), // 0xdd
+    op("DEC", AbsoluteX, 7, Modify(CPU6502::dec)), // 0xde
+    op("DCP", AbsoluteX, 7, Modify(CPU6502::dcp)).undocumented(), // 0xdf
+    op("CPX", Immediate, 2, Read(CPU6502::cpx)), // 0xe0
+    op("SBC", IndirectX, 6, Read(CPU6502::sbc)), // 0xe1
+    op("NOP", Immediate, 2, Read(CPU6502::nop_read)).undocumented(), // 0xe2
+    op("ISC", IndirectX, 8, Modify(CPU6502::isc)).undocumented(), // 0xe3
+    op("CPX", ZeroPage, 3, Read(CPU6502::cpx)), // 0xe4
+    op("SBC", ZeroPage, 3, Read(CPU6502::sbc)), // 0xe5
+    op("INC", ZeroPage, 5, Modify(CPU6502::inc)), // 0xe6
+    op("ISC", ZeroPage, 5, Modify(CPU6502::isc)).undocumented(), // 0xe7
+    op("INX", Implied, 2, Register(CPU6502::inx)), // 0xe8
+    op("SBC", Immediate, 2, Read(CPU6502::sbc)), // 0xe9
+    op("NOP", Implied, 2, Register(CPU6502::nop)), // 0xea
+    op("SBC", Immediate, 2, Read(CPU6502::sbc)).undocumented(), // 0xeb
+    op("CPX", Absolute, 4, Read(CPU6502::cpx)), // 0xec
+    op("SBC", Absolute, 4, Read(CPU6502::sbc)), // 0xed
+    op("INC", Absolute, 6, Modify(CPU6502::inc)), // 0xee
+    op("ISC", Absolute, 6, Modify(CPU6502::isc)).undocumented(), // 0xef
+    op("BEQ", Relative, 2, Branch(CPU6502::beq)), // 0xf0
+    op("SBC", IndirectY, 5, Read(CPU6502::sbc)), // 0xf1
+    op("JAM", Implied, 2, Register(CPU6502::jam)).undocumented(), // 0xf2
+    op("ISC", IndirectY, 8, Modify(CPU6502::isc)).undocumented(), // 0xf3
+    op("NOP", ZeroPageX, 4, Read(CPU6502::nop_read)).undocumented(), // 0xf4
+    op("SBC", ZeroPageX, 4, Read(CPU6502::sbc)), // 0xf5
+    op("INC", ZeroPageX, 6, Modify(CPU6502::inc)), // 0xf6
+    op("ISC", ZeroPageX, 6, Modify(CPU6502::isc)).undocumented(), // 0xf7
+    op("SED", Implied, 2, Register(CPU6502::sed)), // 0xf8
+    op("SBC", AbsoluteY, 4, Read(CPU6502::sbc)), // 0xf9
+    op("NOP", Implied, 2, Register(CPU6502::nop)).undocumented(), // 0xfa
+    op("ISC", AbsoluteY, 7, Modify(CPU6502::isc)).undocumented(), // 0xfb
+    op("NOP", AbsoluteX, 4, Read(CPU6502::nop_read)).undocumented(), // 0xfc
+    op("SBC", AbsoluteX, 4, Read(CPU6502::sbc)), // 0xfd
+    op("INC", AbsoluteX, 7, Modify(CPU6502::inc)), // 0xfe
+    op("ISC", AbsoluteX, 7, Modify(CPU6502::isc)).undocumented(), // 0xff
+];
+
+
+/// The 65C02 shares the documented opcodes, fixes some timings and turns the undocumented ones into
+/// new instructions or NOPs of fixed length.
+const fn cmos() -> [Opcode; 256]{
+    let mut table = NMOS;
+    let mut i = 0;
+    while i < 0x100{
+        table[i].undocumented = false; //the ones not replaced below are documented NOPs, like $44
+        i += 1;
+    }
+    i = 0x03;
+    while i < 0x100{
+        table[i] = op("NOP", Implied, 1, Register(CPU6502::nop)); //x3 and xb take a single cycle
+        i += 0x08;
+    }
+    table[0x02] = op("NOP", Immediate, 2, Read(CPU6502::nop_read));
+    table[0x04] = op("TSB", ZeroPage, 5, Modify(CPU6502::tsb));
+    table[0x07] = op("RMB0", ZeroPage, 5, Modify(CPU6502::rmb::<0>));
+    table[0x0c] = op("TSB", Absolute, 6, Modify(CPU6502::tsb));
+    table[0x0f] = op("BBR0", ZeroPageRelative, 5, Branch(CPU6502::bbr::<0>));
+    table[0x12] = op("ORA", ZeroPageIndirect, 5, Read(CPU6502::ora));
+    table[0x14] = op("TRB", ZeroPage, 5, Modify(CPU6502::trb));
+    table[0x17] = op("RMB1", ZeroPage, 5, Modify(CPU6502::rmb::<1>));
+    table[0x1a] = op("INC", Accumulator, 2, Modify(CPU6502::inc));
+    table[0x1c] = op("TRB", Absolute, 6, Modify(CPU6502::trb));
+    table[0x1e] = op("ASL", AbsoluteX, 6, Modify(CPU6502::asl)).with_page_penalty();
+    table[0x1f] = op("BBR1", ZeroPageRelative, 5, Branch(CPU6502::bbr::<1>));
+    table[0x22] = op("NOP", Immediate, 2, Read(CPU6502::nop_read));
+    table[0x27] = op("RMB2", ZeroPage, 5, Modify(CPU6502::rmb::<2>));
+    table[0x2f] = op("BBR2", ZeroPageRelative, 5, Branch(CPU6502::bbr::<2>));
+    table[0x32] = op("AND", ZeroPageIndirect, 5, Read(CPU6502::and));
+    table[0x34] = op("BIT", ZeroPageX, 4, Read(CPU6502::bit));
+    table[0x37] = op("RMB3", ZeroPage, 5, Modify(CPU6502::rmb::<3>));
+    table[0x3a] = op("DEC", Accumulator, 2, Modify(CPU6502::dec));
+    table[0x3c] = op("BIT", AbsoluteX, 4, Read(CPU6502::bit));
+    table[0x3e] = op("ROL", AbsoluteX, 6, Modify(CPU6502::rol)).with_page_penalty();
+    table[0x3f] = op("BBR3", ZeroPageRelative, 5, Branch(CPU6502::bbr::<3>));
+    table[0x42] = op("NOP", Immediate, 2, Read(CPU6502::nop_read));
+    table[0x47] = op("RMB4", ZeroPage, 5, Modify(CPU6502::rmb::<4>));
+    table[0x4f] = op("BBR4", ZeroPageRelative, 5, Branch(CPU6502::bbr::<4>));
+    table[0x52] = op("EOR", ZeroPageIndirect, 5, Read(CPU6502::eor));
+    table[0x57] = op("RMB5", ZeroPage, 5, Modify(CPU6502::rmb::<5>));
+    table[0x5a] = op("PHY", Implied, 3, Control(CPU6502::phy));
+    table[0x5c] = op("NOP", Absolute, 8, Read(CPU6502::nop_read));
+    table[0x5e] = op("LSR", AbsoluteX, 6, Modify(CPU6502::lsr)).with_page_penalty();
+    table[0x5f] = op("BBR5", ZeroPageRelative, 5, Branch(CPU6502::bbr::<5>));
+    table[0x62] = op("NOP", Immediate, 2, Read(CPU6502::nop_read));
+    table[0x64] = op("STZ", ZeroPage, 3, Write(CPU6502::stz));
+    table[0x67] = op("RMB6", ZeroPage, 5, Modify(CPU6502::rmb::<6>));
+    table[0x6c] = op("JMP", Indirect, 6, Control(CPU6502::jmp));
+    table[0x6f] = op("BBR6", ZeroPageRelative, 5, Branch(CPU6502::bbr::<6>));
+    table[0x72] = op("ADC", ZeroPageIndirect, 5, Read(CPU6502::adc));
+    table[0x74] = op("STZ", ZeroPageX, 4, Write(CPU6502::stz));
+    table[0x77] = op("RMB7", ZeroPage, 5, Modify(CPU6502::rmb::<7>));
+    table[0x7a] = op("PLY", Implied, 4, Control(CPU6502::ply));
+    table[0x7c] = op("JMP", AbsoluteIndirectX, 6, Control(CPU6502::jmp));
+    table[0x7e] = op("ROR", AbsoluteX, 6, Modify(CPU6502::ror)).with_page_penalty();
+    table[0x7f] = op("BBR7", ZeroPageRelative, 5, Branch(CPU6502::bbr::<7>));
+    table[0x80] = op("BRA", Relative, 2, Branch(CPU6502::bra));
+    table[0x87] = op("SMB0", ZeroPage, 5, Modify(CPU6502::smb::<0>));
+    table[0x89] = op("BIT", Immediate, 2, Read(CPU6502::bit_immediate));
+    table[0x8f] = op("BBS0", ZeroPageRelative, 5, Branch(CPU6502::bbs::<0>));
+    table[0x92] = op("STA", ZeroPageIndirect, 5, Write(CPU6502::sta));
+    table[0x97] = op("SMB1", ZeroPage, 5, Modify(CPU6502::smb::<1>));
+    table[0x9c] = op("STZ", Absolute, 4, Write(CPU6502::stz));
+    table[0x9e] = op("STZ", AbsoluteX, 5, Write(CPU6502::stz));
+    table[0x9f] = op("BBS1", ZeroPageRelative, 5, Branch(CPU6502::bbs::<1>));
+    table[0xa7] = op("SMB2", ZeroPage, 5, Modify(CPU6502::smb::<2>));
+    table[0xaf] = op("BBS2", ZeroPageRelative, 5, Branch(CPU6502::bbs::<2>));
+    table[0xb2] = op("LDA", ZeroPageIndirect, 5, Read(CPU6502::lda));
+    table[0xb7] = op("SMB3", ZeroPage, 5, Modify(CPU6502::smb::<3>));
+    table[0xbf] = op("BBS3", ZeroPageRelative, 5, Branch(CPU6502::bbs::<3>));
+    table[0xc7] = op("SMB4", ZeroPage, 5, Modify(CPU6502::smb::<4>));
+    table[0xcb] = op("WAI", Implied, 3, Register(CPU6502::wai));
+    table[0xcf] = op("BBS4", ZeroPageRelative, 5, Branch(CPU6502::bbs::<4>));
+    table[0xd2] = op("CMP", ZeroPageIndirect, 5, Read(CPU6502::cmp));
+    table[0xd7] = op("SMB5", ZeroPage, 5, Modify(CPU6502::smb::<5>));
+    table[0xda] = op("PHX", Implied, 3, Control(CPU6502::phx));
+    table[0xdb] = op("STP", Implied, 3, Register(CPU6502::stp));
+    table[0xdc] = op("NOP", Absolute, 4, Read(CPU6502::nop_read));
+    table[0xdf] = op("BBS5", ZeroPageRelative, 5, Branch(CPU6502::bbs::<5>));
+    table[0xe7] = op("SMB6", ZeroPage, 5, Modify(CPU6502::smb::<6>));
+    table[0xef] = op("BBS6", ZeroPageRelative, 5, Branch(CPU6502::bbs::<6>));
+    table[0xf2] = op("SBC", ZeroPageIndirect, 5, Read(CPU6502::sbc));
+    table[0xf7] = op("SMB7", ZeroPage, 5, Modify(CPU6502::smb::<7>));
+    table[0xfa] = op("PLX", Implied, 4, Control(CPU6502::plx));
+    table[0xfc] = op("NOP", Absolute, 4, Read(CPU6502::nop_read));
+    table[0xff] = op("BBS7", ZeroPageRelative, 5, Branch(CPU6502::bbs::<7>));
+    table
+}
+
+impl CPU6502{
+    fn lda(&mut self, value: u8){
+        self.A = value;
+        self.P.set_NZ(value);
+    }
+
+    fn ldx(&mut self, value: u8){
+        self.X = value;
+        self.P.set_NZ(value);
+    }
+
+    fn ldy(&mut self, value: u8){
+        self.Y = value;
+        self.P.set_NZ(value);
+    }
+
+    fn ora(&mut self, value: u8){
+        self.A |= value;
+        self.P.set_NZ(self.A);
+    }
+
+    fn and(&mut self, value: u8){
+        self.A &= value;
+        self.P.set_NZ(self.A);
+    }
+
+    fn eor(&mut self, value: u8){
+        self.A ^= value;
+        self.P.set_NZ(self.A);
+    }
+
+    fn cmp(&mut self, value: u8){
+        self.compare(self.A, value);
+    }
+
+    fn cpx(&mut self, value: u8){
+        self.compare(self.X, value);
+    }
+
+    fn cpy(&mut self, value: u8){
+        self.compare(self.Y, value);
+    }
+
+    fn bit(&mut self, value: u8){
+        self.P.set_Z(value & self.A == 0);
+        self.P.value = (self.P.value & 0b0011_1111) | (value & 0b1100_0000);
+    }
+
+    /// 65C02 BIT Immediate only touches Z.
+    fn bit_immediate(&mut self, value: u8){
+        self.P.set_Z(value & self.A == 0);
+    }
+
+    fn nop_read(&mut self, _value: u8){
+    }
+
+    fn lax(&mut self, value: u8){
+        self.A = value;
+        self.X = value;
+        self.P.set_NZ(value);
+    }
+
+    fn anc(&mut self, value: u8){
+        self.and(value);
+        self.P.set_C(self.P.get_N());
+    }
+
+    fn alr(&mut self, value: u8){
+        self.A = self.lsr(self.A & value);
+    }
+
+    /// Unstable, the magic constant depends on the chip.
+    fn ane(&mut self, value: u8){
+        self.A = (self.A | self.unstable.ane) & self.X & value;
+        self.P.set_NZ(self.A);
+    }
+
+    /// Unstable, the magic constant depends on the chip.
+    fn lxa(&mut self, value: u8){
+        self.A = (self.A | self.unstable.lxa) & value;
+        self.X = self.A;
+        self.P.set_NZ(self.A);
+    }
+
+    fn sbx(&mut self, value: u8){
+        let r = (self.A & self.X).overflowing_sub(value);
+        self.X = r.0;
+        self.P.set_NZ(r.0);
+        self.P.set_C(!r.1);
+    }
+
+    fn las(&mut self, value: u8){
+        let data = value & self.SP;
+        self.A = data;
+        self.X = data;
+        self.SP = data;
+        self.P.set_NZ(data);
+    }
+
+    fn sta(&mut self) -> u8{
+        self.A
+    }
+
+    fn stx(&mut self) -> u8{
+        self.X
+    }
+
+    fn sty(&mut self) -> u8{
+        self.Y
+    }
+
+    fn stz(&mut self) -> u8{
+        0
+    }
+
+    fn sax(&mut self) -> u8{
+        self.A & self.X
+    }
+
+    fn inc(&mut self, value: u8) -> u8{
+        let data = value.overflowing_add(1).0;
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn dec(&mut self, value: u8) -> u8{
+        let data = value.overflowing_sub(1).0;
+        self.P.set_NZ(data);
+        data
+    }
+
+    fn slo(&mut self, value: u8) -> u8{
+        let data = self.asl(value);
+        self.ora(data);
+        data
+    }
+
+    fn rla(&mut self, value: u8) -> u8{
+        let data = self.rol(value);
+        self.and(data);
+        data
+    }
+
+    fn sre(&mut self, value: u8) -> u8{
+        let data = self.lsr(value);
+        self.eor(data);
+        data
+    }
+
+    fn rra(&mut self, value: u8) -> u8{
+        let data = self.ror(value);
+        self.adc(data);
+        data
+    }
+
+    fn dcp(&mut self, value: u8) -> u8{
+        let data = value.overflowing_sub(1).0;
+        self.compare(self.A, data);
+        data
+    }
+
+    fn isc(&mut self, value: u8) -> u8{
+        let data = value.overflowing_add(1).0;
+        self.sbc(data);
+        data
+    }
+
+    fn tsb(&mut self, value: u8) -> u8{
+        self.P.set_Z(value & self.A == 0);
+        value | self.A
+    }
+
+    fn trb(&mut self, value: u8) -> u8{
+        self.P.set_Z(value & self.A == 0);
+        value & !self.A
+    }
+
+    fn rmb<const BIT: u8>(&mut self, value: u8) -> u8{
+        value & !(1 << BIT)
+    }
+
+    fn smb<const BIT: u8>(&mut self, value: u8) -> u8{
+        value | (1 << BIT)
+    }
+
+    fn clc(&mut self){
+        self.P.set_C(false);
+    }
+
+    fn sec(&mut self){
+        self.P.set_C(true);
+    }
+
+    fn cli(&mut self){
+        self.P.set_I(false);
+    }
+
+    fn sei(&mut self){
+        self.P.set_I(true);
+    }
+
+    fn clv(&mut self){
+        self.P.set_V(false);
+    }
+
+    fn cld(&mut self){
+        self.P.set_D(false);
+    }
+
+    fn sed(&mut self){
+        self.P.set_D(true);
+    }
+
+    fn tax(&mut self){
+        self.ldx(self.A);
+    }
+
+    fn tay(&mut self){
+        self.ldy(self.A);
+    }
+
+    fn txa(&mut self){
+        self.lda(self.X);
+    }
+
+    fn tya(&mut self){
+        self.lda(self.Y);
+    }
+
+    fn tsx(&mut self){
+        self.ldx(self.SP);
+    }
+
+    fn txs(&mut self){
+        self.SP = self.X;
+    }
+
+    fn inx(&mut self){
+        self.X = self.inc(self.X);
+    }
+
+    fn iny(&mut self){
+        self.Y = self.inc(self.Y);
+    }
+
+    fn dex(&mut self){
+        self.X = self.dec(self.X);
+    }
+
+    fn dey(&mut self){
+        self.Y = self.dec(self.Y);
+    }
+
+    fn nop(&mut self){
+    }
+
+    fn jam(&mut self){
+        self.jammed = true;
+    }
+
+    fn wai(&mut self){
+        self.waiting = true;
+    }
+
+    fn stp(&mut self){
+        self.jammed = true;
+    }
+
+    fn bpl(&self, _value: u8) -> bool{
+        !self.P.get_N()
+    }
+
+    fn bmi(&self, _value: u8) -> bool{
+        self.P.get_N()
+    }
+
+    fn bvc(&self, _value: u8) -> bool{
+        !self.P.get_V()
+    }
+
+    fn bvs(&self, _value: u8) -> bool{
+        self.P.get_V()
+    }
+
+    fn bcc(&self, _value: u8) -> bool{
+        !self.P.get_C()
+    }
+
+    fn bcs(&self, _value: u8) -> bool{
+        self.P.get_C()
+    }
+
+    fn bne(&self, _value: u8) -> bool{
+        !self.P.get_Z()
+    }
+
+    fn beq(&self, _value: u8) -> bool{
+        self.P.get_Z()
+    }
+
+    fn bra(&self, _value: u8) -> bool{
+        true
+    }
+
+    fn bbr<const BIT: u8>(&self, value: u8) -> bool{
+        value & (1 << BIT) == 0
+    }
+
+    fn bbs<const BIT: u8>(&self, value: u8) -> bool{
+        value & (1 << BIT) != 0
+    }
+
+    fn brk(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.PC = self.PC.overflowing_add(1).0; //BRK skips the byte after it
+        self.push_interrupt(InterruptType::BRK, memory).0
+    }
+
+    fn jsr(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        let lo = self.fetch(memory);
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //internal stack cycle
+        let pc = self.PC; //need to push PC+2 not 3 RTS will add 1
+        self.push(memory, (pc >> 8) as u8);
+        self.push(memory, (pc & 0x0ff) as u8);
+        let hi = self.fetch(memory); //high byte is read after the push
+        self.PC = (hi as u16) << 8 | lo as u16;
+        self.PC
+    }
+
+    fn rts(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let lo = self.pull(memory);
+        let hi = self.pull(memory);
+        self.PC = (hi as u16) << 8 | lo as u16;
+        self.fetch(memory); //read of the JSR's last byte while PC is incremented
+        self.PC
+    }
+
+    fn rti(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let data = self.pull(memory);
+        self.P.value = (data & 0b1100_1111) | 0b0010_0000; //Pull flags, ignore B, bit 5 is always 1
+        let lo = self.pull(memory);
+        let hi = self.pull(memory);
+        self.PC = (hi as u16) << 8 | lo as u16;
+        self.PC
+    }
+
+    fn pha(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.push(memory, self.A)
+    }
+
+    fn php(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.push(memory, self.P.value | 0b0011_0000) //B and bit 5 are always pushed set
+    }
+
+    fn phx(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.push(memory, self.X)
+    }
+
+    fn phy(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.push(memory, self.Y)
+    }
+
+    fn pla(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let data = self.pull(memory);
+        self.lda(data);
+        0x0100 | self.SP as u16
+    }
+
+    fn plp(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let data = self.pull(memory);
+        self.P.value = (data & 0xCF) | 0x20; // Pull flags, ignore B, bit 5 is always 1
+        0x0100 | self.SP as u16
+    }
+
+    fn plx(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let data = self.pull(memory);
+        self.ldx(data);
+        0x0100 | self.SP as u16
+    }
+
+    fn ply(&mut self, _mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.dummy_read(memory, 0x0100 | self.SP as u16); //read before SP is incremented
+        let data = self.pull(memory);
+        self.ldy(data);
+        0x0100 | self.SP as u16
+    }
+
+    fn jmp(&mut self, mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        self.PC = self.get_address(mode, Access::Read, memory);
+        self.PC
+    }
+
+    /// Unstable.
+    fn sha(&mut self, mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        let address = self.get_address(mode, Access::Write, memory);
+        self.store_high_and(address, self.A & self.X, memory)
+    }
+
+    /// Unstable.
+    fn shx(&mut self, mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        let address = self.get_address(mode, Access::Write, memory);
+        self.store_high_and(address, self.X, memory)
+    }
+
+    /// Unstable.
+    fn shy(&mut self, mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        let address = self.get_address(mode, Access::Write, memory);
+        self.store_high_and(address, self.Y, memory)
+    }
+
+    /// Unstable.
+    fn tas(&mut self, mode: AdressingType, memory: &mut dyn Memory6502) -> u16{
+        let address = self.get_address(mode, Access::Write, memory);
+        self.SP = self.A & self.X;
+        self.store_high_and(address, self.SP, memory)
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::callstack::{CallStack, FrameKind};
+use super::disassembler::Instruction;
+use super::memory::Memory6502;
+use super::trace::TraceSink;
+use super::{CPUState, CpuVariant};
+
+/// Counters of the instructions starting at one address.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct PcProfile{
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+/// Profiles the program running on the emulated CPU, feed it through `run_single_traced`.
+///
+/// Cycles go to the call stack the instruction started in, so a JSR counts for its caller and an RTS for the subroutine.
+/// Interrupt sequences count for the interrupted code but not for any address or opcode.
+pub struct Profiler{
+    pcs: Vec<PcProfile>,
+    bytes: Vec<[u8; 3]>,
+    variant: CpuVariant,
+    opcodes: [u64; 256],
+    instructions: u64,
+    interrupts: u64,
+    cycles: u64,
+    stack: CallStack,
+    stacks: HashMap<Vec<(FrameKind, u16)>, usize>,
+    stack_cycles: Vec<u64>,
+    current: usize,
+    labels: HashMap<u16, String>,
+}
+
+impl Default for Profiler{
+    fn default() -> Self{
+        Profiler::new()
+    }
+}
+
+impl Profiler{
+    pub fn new() -> Self{
+        Profiler { pcs: vec![PcProfile::default(); 0x10000], bytes: vec![[0; 3]; 0x10000], variant: CpuVariant::Nmos6502, opcodes: [0; 256],
+            instructions: 0, interrupts: 0, cycles: 0, stack: CallStack::new(), stacks: HashMap::from([(Vec::new(), 0)]), stack_cycles: vec![0],
+            current: 0, labels: HashMap::new() }
+    }
+
+    /// Names a subroutine or handler in the folded stacks instead of its address.
+    pub fn add_label(&mut self, address: u16, name: &str){
+        self.labels.insert(address, name.to_owned());
+    }
+
+    pub fn pc(&self, pc: u16) -> PcProfile{
+        self.pcs[pc as usize]
+    }
+
+    /// Executed instructions per opcode.
+    pub fn opcode_histogram(&self) -> &[u64; 256]{
+        &self.opcodes
+    }
+
+    pub fn instructions(&self) -> u64{
+        self.instructions
+    }
+
+    pub fn interrupts(&self) -> u64{
+        self.interrupts
+    }
+
+    pub fn cycles(&self) -> u64{
+        self.cycles
+    }
+
+    /// The shadow call stack as of the last instruction.
+    pub fn call_stack(&self) -> &CallStack{
+        &self.stack
+    }
+
+    /// The `count` addresses that took most cycles, most first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(u16, PcProfile)>{
+        let mut spots: Vec<(u16, PcProfile)> = self.pcs.iter().enumerate()
+            .filter(|(_, profile)| profile.instructions > 0)
+            .map(|(pc, profile)| (pc as u16, *profile))
+            .collect();
+        spots.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        spots.truncate(count);
+        spots
+    }
+
+    /// Totals, the `count` hottest addresses and the `count` most used opcodes.
+    pub fn write_report<W: Write>(&self, out: &mut W, count: usize) -> io::Result<()>{
+        writeln!(out, "{} instructions, {} cycles, {} interrupts", self.instructions, self.cycles, self.interrupts)?;
+        writeln!(out, "Hot spots:")?;
+        writeln!(out, "{:>12} {:>6} {:>12}  address", "cycles", "%", "instructions")?;
+        for (pc, profile) in self.hot_spots(count){
+            let instruction = Instruction::decode(pc, self.bytes[pc as usize], self.variant);
+            writeln!(out, "{:>12} {:>6.2} {:>12}  {:04X}  {}", profile.cycles, self.percent(profile.cycles), profile.instructions, pc, instruction)?;
+        }
+
+        let mut opcodes: Vec<(usize, u64)> = self.opcodes.iter().copied().enumerate().filter(|(_, n)| *n > 0).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        writeln!(out, "Opcodes:")?;
+        for (opcode, n) in opcodes.into_iter().take(count){
+            let mnemonic = Instruction::decode(0, [opcode as u8, 0, 0], self.variant).mnemonic;
+            writeln!(out, "{:>12} {:>6.2}  {:02X}  {}", n, n as f64 * 100.0 / self.instructions.max(1) as f64, opcode, mnemonic)?;
+        }
+        Ok(())
+    }
+
+    /// Cycles per call stack in Brendan Gregg's folded format, one `root;caller;callee cycles` line per stack.
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> io::Result<()>{
+        let mut lines: Vec<String> = self.stacks.iter()
+            .filter(|(_, index)| self.stack_cycles[**index] > 0)
+            .map(|(stack, index)| {
+                let mut line = String::from("root");
+                for (kind, function) in stack{
+                    line.push(';');
+                    line.push_str(&self.frame_name(*kind, *function));
+                }
+                format!("{} {}", line, self.stack_cycles[*index])
+            })
+            .collect();
+        lines.sort();
+        for line in lines{
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn frame_name(&self, kind: FrameKind, function: u16) -> String{
+        if let Some(label) = self.labels.get(&function){
+            return label.clone();
+        }
+        match kind {
+            FrameKind::Jsr => format!("{:04X}", function),
+            FrameKind::Brk => format!("BRK_{:04X}", function),
+            FrameKind::Irq => format!("IRQ_{:04X}", function),
+            FrameKind::Nmi => format!("NMI_{:04X}", function),
+        }
+    }
+
+    fn percent(&self, cycles: u64) -> f64{
+        cycles as f64 * 100.0 / self.cycles.max(1) as f64
+    }
+}
+
+impl TraceSink for Profiler{
+    fn trace(&mut self, state: &CPUState, _memory: &dyn Memory6502){
+        let cycles = state.elapsed_cycles() as u64;
+        self.cycles += cycles;
+        self.stack_cycles[self.current] += cycles;
+        if state.interrupt().is_some(){
+            self.interrupts += 1;
+        }
+        else{
+            let pc = state.registers_before().PC as usize;
+            self.pcs[pc].instructions += 1;
+            self.pcs[pc].cycles += cycles;
+            self.bytes[pc] = state.bytes();
+            self.variant = state.variant();
+            self.opcodes[state.opcode() as usize] += 1;
+            self.instructions += 1;
+        }
+
+        if self.stack.update(state){
+            let key: Vec<(FrameKind, u16)> = self.stack.frames().iter().map(|frame| (frame.kind, frame.function)).collect();
+            let next = self.stack_cycles.len();
+            self.current = *self.stacks.entry(key).or_insert(next);
+            if self.current == next{
+                self.stack_cycles.push(0);
+            }
+        }
+    }
+}
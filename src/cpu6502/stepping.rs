@@ -0,0 +1,81 @@
+use super::memory::Memory6502;
+use super::trace::TraceSink;
+use super::{CPUState, CpuRegisters};
+
+/// Debugger commands that run more than one instruction, see `RunUntil`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum RunCommand{
+    /// Runs a JSR, BRK or interrupt sequence until it returns, anything else is a single step.
+    StepOver,
+    /// Runs until the subroutine or interrupt handler we are in returns.
+    StepOut,
+    /// Single steps, an interrupt sequence counts as one.
+    Instructions(u64),
+    /// Runs until at least this many cycles passed.
+    Cycles(u64),
+    /// Runs until PC is at the address.
+    Address(u16),
+}
+
+/// Follows a `RunCommand`: feed it every step through `run_single_traced`, then ask `is_done` with the registers after it.
+///
+/// Returns are told by SP. A call is over when PC is back at its return address with SP where it was, a step out
+/// when an RTS or RTI lifts SP above where it was at the start. Calls nested inside, interrupts and PHA/PHA/RTS
+/// jumps never get there, so they run through.
+#[derive(Clone,Copy,Debug)]
+pub struct RunUntil{
+    command: RunCommand,
+    sp: u8,
+    cycles: u64,
+    steps: u64,
+    return_to: Option<u16>,
+    returned: bool,
+}
+
+impl RunUntil{
+    /// Starts from the registers and cycle counter before the first step.
+    pub fn new(command: RunCommand, registers: &CpuRegisters, cycles: u64) -> Self{
+        RunUntil { command, sp: registers.SP, cycles, steps: 0, return_to: None, returned: false }
+    }
+
+    pub fn command(&self) -> RunCommand{
+        self.command
+    }
+
+    /// Steps traced so far.
+    pub fn steps(&self) -> u64{
+        self.steps
+    }
+
+    /// True when the registers and cycle counter after the last step complete the command.
+    pub fn is_done(&self, registers: &CpuRegisters, cycles: u64) -> bool{
+        if self.steps == 0{
+            return false;
+        }
+        match self.command {
+            RunCommand::StepOver => self.return_to.is_none_or(|pc| registers.PC == pc && registers.SP >= self.sp),
+            RunCommand::StepOut => self.returned,
+            RunCommand::Instructions(count) => self.steps >= count,
+            RunCommand::Cycles(count) => cycles >= self.cycles.saturating_add(count),
+            RunCommand::Address(pc) => registers.PC == pc,
+        }
+    }
+}
+
+impl TraceSink for RunUntil{
+    fn trace(&mut self, state: &CPUState, _memory: &dyn Memory6502){
+        let before = state.registers_before();
+        if self.steps == 0 && self.command == RunCommand::StepOver{
+            self.return_to = match (state.interrupt(), state.opcode()) {
+                (Some(_), _) => Some(before.PC),
+                (None, 0x20) => Some(before.PC.overflowing_add(3).0),
+                (None, 0x00) => Some(before.PC.overflowing_add(2).0),
+                _ => None,
+            };
+        }
+        if state.interrupt().is_none() && matches!(state.opcode(), 0x40 | 0x60) && state.registers().SP > self.sp{
+            self.returned = true;
+        }
+        self.steps += 1;
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::io;
+
+/// How far past a symbol an address is still shown as `name+offset`.
+const MAX_OFFSET: u16 = 0x100;
+
+/// Names for addresses, used by backtraces.
+#[derive(Clone,Debug,Default)]
+pub struct Symbols{
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols{
+    pub fn new() -> Self{
+        Symbols { names: BTreeMap::new() }
+    }
+
+    /// Reads VICE `al C:080d .start` lines and assembler `start = $080d` lines, anything else is skipped.
+    pub fn parse(text: &str) -> Symbols{
+        let mut symbols = Symbols::new();
+        for line in text.lines(){
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (address, name) = match words.as_slice() {
+                ["al", address, name] => (address.trim_start_matches("C:"), name.trim_start_matches('.')),
+                [name, "=", address] => (address.trim_start_matches('$'), *name),
+                _ => continue,
+            };
+            if let Ok(address) = u16::from_str_radix(address, 16){
+                symbols.insert(address, name);
+            }
+        }
+        symbols
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> io::Result<Symbols>{
+        Ok(Symbols::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str){
+        self.names.insert(address, name.to_owned());
+    }
+
+    pub fn get(&self, address: u16) -> Option<&str>{
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize{
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.names.is_empty()
+    }
+
+    /// `name`, `name+offset` shortly after a symbol, `$XXXX` without one.
+    pub fn describe(&self, address: u16) -> String{
+        match self.names.range(..=address).next_back() {
+            Some((start, name)) if *start == address => name.clone(),
+            Some((start, name)) if address - start < MAX_OFFSET => format!("{}+{}", name, address - start),
+            _ => format!("${:04X}", address),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use core::fmt::Write as _;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "std")]
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "std")]
+use super::memory::Bank;
+use super::memory::Memory6502;
+use super::{CPUState, InterruptType};
+
+/// Receives every instruction and interrupt sequence from `CPU6502::run_single_traced`.
+pub trait TraceSink{
+    /// `memory` is the bus after the instruction, only for `peek`, `bank` and other calls that do not touch devices.
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502);
+}
+
+/// No sink, for the callers of `run_command` and the like that do not trace.
+impl TraceSink for (){
+    fn trace(&mut self, _state: &CPUState, _memory: &dyn Memory6502){}
+}
+
+/// Lets an optional sink be passed along, e.g. one only set up from the command line.
+impl<T: TraceSink> TraceSink for Option<T>{
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502){
+        if let Some(sink) = self{
+            sink.trace(state, memory);
+        }
+    }
+}
+
+/// Lends a sink for a while, e.g. to pair it with another one.
+impl<T: TraceSink + ?Sized> TraceSink for &mut T{
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502){
+        (**self).trace(state, memory);
+    }
+}
+
+/// Feeds two sinks, nest pairs for more.
+impl<A: TraceSink, B: TraceSink> TraceSink for (A, B){
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502){
+        self.0.trace(state, memory);
+        self.1.trace(state, memory);
+    }
+}
+
+/// One trace line, the format stays stable so logs can be compared between runs and against other emulators:
+///
+/// `C000  4C F5 C5  JMP $C5F5        EA:C5F5 A:00 X:00 Y:00 P:24 SP:FD CYC:7`
+///
+/// Registers are the ones the instruction started with and CYC the cycle it started on, like in VICE and nestest logs.
+/// EA is the effective address, branch target or interrupt handler. Hardware interrupts show as `IRQ` or `NMI`.
+pub struct TraceLine<'a>(pub &'a CPUState);
+
+/// Writes a column of the line with hex digits in upper case, `pad` fills it up to a width.
+struct Column<'a, 'b>{
+    fmt: &'a mut core::fmt::Formatter<'b>,
+    len: usize,
+}
+
+impl core::fmt::Write for Column<'_, '_>{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result{
+        for c in s.chars(){
+            self.fmt.write_char(c.to_ascii_uppercase())?;
+        }
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+impl Column<'_, '_>{
+    fn pad(&mut self, width: usize) -> core::fmt::Result{
+        while self.len < width{
+            self.fmt.write_char(' ')?;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Display for TraceLine<'_>{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        let state = self.0;
+        let registers = state.registers_before();
+        write!(fmt, "{:04X}  ", registers.PC)?;
+
+        let mut column = Column { fmt, len: 0 };
+        match state.interrupt() {
+            Some(int) => {
+                column.pad(10)?;
+                column.write_str(if int == InterruptType::NMI { "NMI" } else { "IRQ" })?;
+            }
+            None => {
+                let instruction = state.disassemble();
+                for byte in &instruction.bytes[..instruction.length as usize]{
+                    write!(column, "{:02X} ", byte)?;
+                }
+                column.pad(10)?;
+                write!(column, "{}", instruction)?;
+            }
+        }
+        column.pad(27)?;
+
+        write!(fmt, "EA:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            state.address(), registers.A, registers.X, registers.Y, registers.P.value(), registers.SP, state.cycles())
+    }
+}
+
+/// Which instructions a `TraceLog` writes, the default lets everything through.
+#[cfg(feature = "std")]
+#[derive(Clone,Debug,Default)]
+pub struct TraceFilter{
+    /// Only instructions starting inside one of these, every address when empty.
+    pub ranges: Vec<RangeInclusive<u16>>,
+    /// Only instructions running from this kind of memory, e.g. `Bank::Ram` to skip the ROMs.
+    pub bank: Option<Bank>,
+    /// Only JSR, RTS, RTI, BRK and hardware interrupts.
+    pub calls_only: bool,
+    /// Logging starts when PC reaches this address, right away when None.
+    pub start: Option<u16>,
+    /// Logging stops after the instruction at this address, it starts again at `start`.
+    pub stop: Option<u16>,
+}
+
+#[cfg(feature = "std")]
+impl TraceFilter{
+    /// Whether `state` passes the address, bank and kind filters, triggers are left to the log.
+    pub fn matches(&self, state: &CPUState, memory: &dyn Memory6502) -> bool{
+        let pc = state.registers_before().PC;
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|range| range.contains(&pc)){
+            return false;
+        }
+        if self.bank.is_some_and(|bank| memory.bank(pc) != bank){
+            return false;
+        }
+        !self.calls_only || state.interrupt().is_some() || matches!(state.opcode(), 0x00 | 0x20 | 0x40 | 0x60)
+    }
+}
+
+/// Streams `TraceLine`s to a writer, one per line.
+/// Write errors stop the log, `finish` reports the first one.
+#[cfg(feature = "std")]
+pub struct TraceLog<W: Write>{
+    writer: W,
+    filter: TraceFilter,
+    active: bool,
+    lines: u64,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl TraceLog<BufWriter<std::fs::File>>{
+    /// Creates or truncates the file at `path`.
+    pub fn create<P: AsRef<std::path::Path>>(path: P, filter: TraceFilter) -> io::Result<Self>{
+        Ok(TraceLog::new(BufWriter::new(std::fs::File::create(path)?), filter))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> TraceLog<W>{
+    pub fn new(writer: W, filter: TraceFilter) -> Self{
+        let active = filter.start.is_none();
+        TraceLog { writer, filter, active, lines: 0, error: None }
+    }
+
+    /// Number of lines written so far.
+    pub fn lines(&self) -> u64{
+        self.lines
+    }
+
+    /// Whether the start trigger was hit and the stop trigger was not.
+    pub fn is_active(&self) -> bool{
+        self.active
+    }
+
+    /// Flushes the writer and hands it back.
+    pub fn finish(mut self) -> io::Result<W>{
+        if let Some(error) = self.error.take(){
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> TraceSink for TraceLog<W>{
+    fn trace(&mut self, state: &CPUState, memory: &dyn Memory6502){
+        let pc = state.registers_before().PC;
+        if !self.active && self.filter.start == Some(pc){
+            self.active = true;
+        }
+        if self.active && self.error.is_none() && self.filter.matches(state, memory){
+            match writeln!(self.writer, "{}", TraceLine(state)) {
+                Ok(()) => self.lines += 1,
+                Err(error) => self.error = Some(error),
+            }
+        }
+        if self.active && self.filter.stop == Some(pc){
+            self.active = false;
+        }
+    }
+}
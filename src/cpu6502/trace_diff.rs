@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use super::memory::Memory6502;
+use super::trace::{TraceLine, TraceSink};
+use super::CPUState;
+
+/// B and bit 5 are not stored in P, emulators show them differently.
+const P_MASK: u8 = 0b1100_1111;
+
+/// Registers of one line of a reference log, fields the log does not have are None.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct TraceRecord{
+    pub pc: u16,
+    pub a: Option<u8>,
+    pub x: Option<u8>,
+    pub y: Option<u8>,
+    pub sp: Option<u8>,
+    pub p: Option<u8>,
+    pub cycles: Option<u64>,
+}
+
+impl TraceRecord{
+    /// Reads a line of a nestest log, of VICE `chis` output or of a `TraceLog`. Lines without an address give None.
+    ///
+    /// `C000  4C F5 C5  JMP $C5F5      A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+    /// `.C:e5cf  A5 C6       LDA $C6        - A:00 X:00 Y:0A SP:f3 ..-..IZC   1234567`
+    pub fn parse(line: &str) -> Option<TraceRecord>{
+        let line = line.trim_start();
+        let line = line.strip_prefix(".C:").unwrap_or(line);
+        if line.as_bytes().get(4).is_some_and(|c| !c.is_ascii_whitespace()){
+            return None;
+        }
+        let mut record = TraceRecord { pc: u16::from_str_radix(line.get(..4)?, 16).ok()?, ..Default::default() };
+
+        let mut registers = false;
+        let mut clock = None;
+        for token in line[4..].split_whitespace(){
+            if let Some((key, value)) = token.split_once(':'){
+                let byte = u8::from_str_radix(value, 16).ok();
+                match key {
+                    "A" => record.a = byte,
+                    "X" => record.x = byte,
+                    "Y" => record.y = byte,
+                    "P" => record.p = byte,
+                    "SP" | "S" => record.sp = byte,
+                    "CYC" => record.cycles = value.parse().ok(),
+                    _ => continue,
+                }
+                registers = true;
+            }
+            else if let Some(p) = TraceRecord::flags(token){
+                record.p = Some(p);
+            }
+            else if registers && token.bytes().all(|c| c.is_ascii_digit()){
+                clock = token.parse().ok(); //VICE ends the line with the clock
+            }
+        }
+        if record.cycles.is_none(){
+            record.cycles = clock;
+        }
+        Some(record)
+    }
+
+    /// VICE shows P as `NV-BDIZC` with dots for clear flags.
+    fn flags(token: &str) -> Option<u8>{
+        if token.len() != 8{
+            return None;
+        }
+        let mut p = 0;
+        for (c, flag) in token.chars().zip("NV-BDIZC".chars()){
+            p <<= 1;
+            if c.eq_ignore_ascii_case(&flag) && flag != '-'{
+                p |= 1;
+            }
+            else if c != '.' && c != '-'{
+                return None;
+            }
+        }
+        Some(p)
+    }
+}
+
+/// Where a run left the reference log, with the lines around it.
+#[derive(Clone,Debug)]
+pub struct Divergence{
+    /// Line of the reference log that did not match.
+    pub line: u64,
+    /// Instructions that matched before it.
+    pub matched: u64,
+    /// The fields that differ, ours first.
+    pub reason: String,
+    /// Our trace around the divergence, in `TraceLine` format.
+    pub ours: Vec<String>,
+    /// The reference log around the divergence.
+    pub reference: Vec<String>,
+    ours_at: usize,
+    reference_at: usize,
+}
+
+impl core::fmt::Display for Divergence{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error>{
+        writeln!(fmt, "Divergence at reference line {} after {} instructions: {}", self.line, self.matched, self.reason)?;
+        writeln!(fmt, "ours:")?;
+        for (i, line) in self.ours.iter().enumerate(){
+            writeln!(fmt, "{} {}", if i == self.ours_at { '>' } else { ' ' }, line)?;
+        }
+        writeln!(fmt, "reference:")?;
+        for (i, line) in self.reference.iter().enumerate(){
+            writeln!(fmt, "{} {}", if i == self.reference_at { '>' } else { ' ' }, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares every instruction against the next line of a reference log, see `TraceRecord::parse` for the formats.
+/// Cycle counts are compared relative to the first line, hardware interrupt sequences are not in the logs and are skipped.
+///
+/// Run the machine with `run_single_traced` until `is_finished`, then `finish` tells where it diverged.
+pub struct TraceDiff<R: BufRead>{
+    reference: R,
+    context: usize,
+    line: u64,
+    matched: u64,
+    cycle_offset: Option<i128>,
+    ours: VecDeque<CPUState>,
+    theirs: VecDeque<String>,
+    diverged: Option<(u64, String, usize, usize)>, //reference line, reason, index in ours and theirs
+    ended: bool,
+    error: Option<io::Error>,
+}
+
+impl TraceDiff<io::BufReader<std::fs::File>>{
+    pub fn open<P: AsRef<std::path::Path>>(path: P, context: usize) -> io::Result<Self>{
+        Ok(TraceDiff::new(io::BufReader::new(std::fs::File::open(path)?), context))
+    }
+}
+
+impl<R: BufRead> TraceDiff<R>{
+    /// Keeps `context` lines of both traces before and after the divergence.
+    pub fn new(reference: R, context: usize) -> Self{
+        TraceDiff { reference, context, line: 0, matched: 0, cycle_offset: None, ours: VecDeque::new(), theirs: VecDeque::new(),
+            diverged: None, ended: false, error: None }
+    }
+
+    /// Instructions that matched the reference so far.
+    pub fn matched(&self) -> u64{
+        self.matched
+    }
+
+    /// True once the reference log ended or the context after a divergence is collected.
+    pub fn is_finished(&self) -> bool{
+        match self.diverged {
+            Some((_, _, ours_at, _)) => self.ours.len() > ours_at + self.context,
+            None => self.ended || self.error.is_some(),
+        }
+    }
+
+    /// The first divergence, None when the run followed the reference to its end.
+    pub fn finish(self) -> io::Result<Option<Divergence>>{
+        if let Some(error) = self.error{
+            return Err(error);
+        }
+        let Some((line, reason, ours_at, reference_at)) = self.diverged else {
+            return Ok(None);
+        };
+        Ok(Some(Divergence {
+            line, matched: self.matched, reason,
+            ours: self.ours.iter().map(|state| TraceLine(state).to_string()).collect(),
+            reference: self.theirs.into_iter().collect(),
+            ours_at, reference_at,
+        }))
+    }
+
+    /// Next line that parses, with its text.
+    fn next_record(&mut self) -> Option<(TraceRecord, String)>{
+        let mut text = String::new();
+        loop{
+            text.clear();
+            match self.reference.read_line(&mut text) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(error) => {
+                    self.error = Some(error);
+                    return None;
+                }
+            }
+            self.line += 1;
+            let text = text.trim_end();
+            if let Some(record) = TraceRecord::parse(text){
+                return Some((record, text.to_owned()));
+            }
+        }
+    }
+
+    fn compare(&mut self, state: &CPUState, record: &TraceRecord) -> String{
+        let registers = state.registers_before();
+        let mut reason = String::new();
+        let mut check = |name: &str, ours: u64, theirs: Option<u64>, width: usize|{
+            if let Some(theirs) = theirs.filter(|theirs| *theirs != ours){
+                if !reason.is_empty(){
+                    reason.push_str(", ");
+                }
+                reason.push_str(&format!("{} {:0width$X} expected {:0width$X}", name, ours, theirs, width = width));
+            }
+        };
+        check("PC", registers.PC as u64, Some(record.pc as u64), 4);
+        check("A", registers.A as u64, record.a.map(u64::from), 2);
+        check("X", registers.X as u64, record.x.map(u64::from), 2);
+        check("Y", registers.Y as u64, record.y.map(u64::from), 2);
+        check("SP", registers.SP as u64, record.sp.map(u64::from), 2);
+        check("P", (registers.P.value() & P_MASK) as u64, record.p.map(|p| (p & P_MASK) as u64), 2);
+
+        if let Some(cycles) = record.cycles{
+            let offset = *self.cycle_offset.get_or_insert(cycles as i128 - state.cycles() as i128);
+            if cycles as i128 - offset != state.cycles() as i128{
+                if !reason.is_empty(){
+                    reason.push_str(", ");
+                }
+                reason.push_str(&format!("CYC {} expected {}", state.cycles() as i128 + offset, cycles));
+            }
+        }
+        reason
+    }
+}
+
+impl<R: BufRead> TraceSink for TraceDiff<R>{
+    fn trace(&mut self, state: &CPUState, _memory: &dyn Memory6502){
+        if self.is_finished(){
+            return;
+        }
+        self.ours.push_back(*state);
+        if self.diverged.is_some(){
+            return;
+        }
+        if self.ours.len() > self.context + 1{
+            self.ours.pop_front();
+        }
+        if state.interrupt().is_some(){
+            return;
+        }
+
+        let Some((record, text)) = self.next_record() else {
+            self.ended = true;
+            return;
+        };
+        self.theirs.push_back(text);
+        if self.theirs.len() > self.context + 1{
+            self.theirs.pop_front();
+        }
+
+        let reason = self.compare(state, &record);
+        if reason.is_empty(){
+            self.matched += 1;
+            return;
+        }
+        self.diverged = Some((self.line, reason, self.ours.len() - 1, self.theirs.len() - 1));
+        for _i in 0..self.context{
+            match self.next_record() {
+                Some((_, text)) => self.theirs.push_back(text),
+                None => break,
+            }
+        }
+    }
+}
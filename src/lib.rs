@@ -0,0 +1,8 @@
+//! 6502 emulator core and the Commodore 64 built around it.
+//! The GUI frontend lives in the binary behind the `gui` feature.
+//! Without the default `std` feature only `cpu6502` is built, as `no_std` and allocation free.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod cpu6502;
+#[cfg(feature = "std")]
+pub mod c64;
@@ -2,13 +2,18 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration,Instant};
 use std::thread;
-use std::sync::mpsc::{channel, TryRecvError};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use macroquad::prelude::*;
 use std::collections::HashSet;
 
-mod c64;
-use c64::C64;
-use c64::c64memory::{C64CharaterRam, C64KeyboadMap};
+use rusty6502::c64::{C64,CpuError};
+use rusty6502::c64::c64memory::{C64CharaterRam, C64KeyboadMap};
+use rusty6502::cpu6502::breakpoints::Breakpoint;
+use rusty6502::cpu6502::coverage::Coverage;
+use rusty6502::cpu6502::profiler::Profiler;
+use rusty6502::cpu6502::stepping::{RunCommand, RunUntil};
+use rusty6502::cpu6502::symbols::Symbols;
+use rusty6502::cpu6502::trace::{TraceFilter, TraceLog};
 
 fn window_conf() -> Conf {
     Conf {
@@ -28,6 +33,65 @@ struct KeysPressed{
     key_codes : HashSet<KeyCode>,
 }
 
+/// What the debugger was asked to do next.
+enum DebugCommand{
+    Step,
+    Continue,
+    Run(RunCommand),
+}
+
+const DEBUG_HELP: &str = "F5 step, F7 continue, F8 step over, F9 step out, F10 next raster line, F11 next frame, \
+    or type i <instructions>, cy <cycles>, g <hex address>, r <raster line>, bt for a backtrace";
+
+fn show_backtrace(c64: &C64, symbols: &Symbols){
+    println!("**** Backtrace ****");
+    c64.call_stack().write_backtrace(&mut std::io::stdout(), c64.registers().PC, symbols).expect("backtrace");
+}
+
+/// Debugger keys in the window.
+fn debug_key(keys: &HashSet<KeyCode>, c64: &C64) -> Option<DebugCommand>{
+    keys.iter().find_map(|key| match key {
+        KeyCode::F5 => Some(DebugCommand::Step),
+        KeyCode::F7 => Some(DebugCommand::Continue),
+        KeyCode::F8 => Some(DebugCommand::Run(RunCommand::StepOver)),
+        KeyCode::F9 => Some(DebugCommand::Run(RunCommand::StepOut)),
+        KeyCode::F10 => Some(DebugCommand::Run(RunCommand::Cycles(c64.cycles_to_raster_line(c64.raster_line() + 1)))),
+        KeyCode::F11 => Some(DebugCommand::Run(RunCommand::Cycles(c64.cycles_to_raster_line(0)))),
+        _ => None,
+    })
+}
+
+/// Debugger commands typed on the terminal, for the ones that take a number.
+fn parse_debug_command(line: &str, c64: &C64) -> Option<DebugCommand>{
+    let mut words = line.split_whitespace();
+    let command = match (words.next()?, words.next()) {
+        ("s", None) => DebugCommand::Step,
+        ("c", None) => DebugCommand::Continue,
+        ("n", None) => DebugCommand::Run(RunCommand::StepOver),
+        ("o", None) => DebugCommand::Run(RunCommand::StepOut),
+        ("i", Some(count)) => DebugCommand::Run(RunCommand::Instructions(count.parse().ok()?)),
+        ("cy", Some(count)) => DebugCommand::Run(RunCommand::Cycles(count.parse().ok()?)),
+        ("g", Some(address)) => DebugCommand::Run(RunCommand::Address(u16::from_str_radix(address.trim_start_matches('$'), 16).ok()?)),
+        ("r", Some(line)) => DebugCommand::Run(RunCommand::Cycles(c64.cycles_to_raster_line(line.parse().ok()?))),
+        ("f", None) => DebugCommand::Run(RunCommand::Cycles(c64.cycles_to_raster_line(0))),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Lines typed on the terminal.
+fn read_stdin_lines() -> Receiver<String>{
+    let (tx, rx) = channel();
+    thread::Builder::new().name("stdin".to_owned()).spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok){
+            if tx.send(line).is_err(){
+                break;
+            }
+        }
+    }).expect("thread spawn error");
+    rx
+}
+
 #[macroquad::main(window_conf)]
 async fn main() {
     let running = Arc::new(AtomicBool::new(true));
@@ -38,9 +102,32 @@ async fn main() {
         r.store(false, Ordering::SeqCst);
     }).expect("Error setting Ctrl-C handler");
 
-    let enable_dbug_at: Option<u16> = None;
-
-    //enable_dbug_at = Some(0xff48);
+    // --break <breakpoint> stops in debug mode, e.g. --break '$ff48' or --break 'write $d020 if A==0', see Breakpoint::parse
+    let mut breakpoints = Vec::new();
+    let mut args = std::env::args();
+    while let Some(arg) = args.next(){
+        if arg == "--break"{
+            let spec = args.next().unwrap_or_default();
+            match Breakpoint::parse(&spec) {
+                Ok(breakpoint) => breakpoints.push(breakpoint),
+                Err(e) => eprintln!("Bad breakpoint '{}': {}", spec, e),
+            }
+        }
+    }
+    // --trace <file> streams every instruction to the file
+    let trace_path = std::env::args().skip_while(|arg| arg != "--trace").nth(1);
+    // --profile <file> writes folded stacks to the file and a hot spot report at exit
+    let profile_path = std::env::args().skip_while(|arg| arg != "--profile").nth(1);
+    // --coverage <file> adds the coverage of this run to the bitmap in the file
+    let coverage_path = std::env::args().skip_while(|arg| arg != "--coverage").nth(1);
+    // --labels <file> names addresses in backtraces, VICE `al C:080d .start` or `start = $080d` lines
+    let symbols = match std::env::args().skip_while(|arg| arg != "--labels").nth(1) {
+        Some(path) => Symbols::load(&path).unwrap_or_else(|e| {
+            eprintln!("Labels error: {}", e);
+            Symbols::new()
+        }),
+        None => Symbols::new(),
+    };
 
     let (fromc64_tx,fromc64_rx) = channel();
     let (to64_tx,to64_rx) = channel::<KeysPressed>();
@@ -52,64 +139,96 @@ async fn main() {
 
     //let c64_font = load_ttf_font("fonts/C64_Pro_Mono-STYLE.ttf").await.expect("c64 font");
 
+    let typed_rx = read_stdin_lines();
+
     let thread_handle = thread::Builder::new().name("C64".to_owned()).spawn(move || {
         let mut cnt = 0;
         let mut c64 = C64::new();
 
         c64.enable_trace(64);
         c64.reset();
+        for breakpoint in breakpoints{
+            c64.breakpoints_mut().add(breakpoint);
+        }
 
         let mut debug_mode = false;
+        // A debugger command running several instructions, debug mode comes back when it is done
+        let mut run_until: Option<RunUntil> = None;
+        let trace_log = trace_path.map(|path| TraceLog::create(path, TraceFilter::default()).expect("trace file"));
+        let profiler = profile_path.as_ref().map(|_| Profiler::new());
+        let coverage = coverage_path.as_ref().map(|_| Coverage::new());
+        let mut sinks = ((trace_log, profiler), coverage);
 
         let character_set = c64.get_character_rom(true);
         fromc64_tx.send(ScreenUpdate::CharsRam(Box::new(character_set.unwrap()))).expect("send");
 
         let mut now = Instant::now();
         while running.load(Ordering::SeqCst){
-            let r = c64.run_single();
+            let r = c64.run_single_traced(&mut (&mut sinks, &mut run_until));
 
             cnt += 1;
 
             match r{
-                Ok(pc) => {
-                    if let Some(debug_at) = enable_dbug_at{
-                        if debug_at == pc{
-                            debug_mode = true;
-                            println!("Entering debug mode at PC={:#06x} F5 to step", pc);
-                        }
-                    }
+                Ok(_) => {},
+                Err(e @ (CpuError::Breakpoint { .. } | CpuError::Watchpoint { .. })) => {
+                    debug_mode = true;
+                    run_until = None;
+                    println!("{} entering debug mode, {}", e, DEBUG_HELP);
+                    show_backtrace(&c64, &symbols);
                 },
                 Err(e) => {
+                    if matches!(e, CpuError::Jam { .. } | CpuError::IllegalOpcode { .. }){
+                        c64.show_debug();
+                        show_backtrace(&c64, &symbols);
+                    }
                     eprintln!("C64 Cpu error: {}", e);
                     break;
                 }
             };
 
+            if run_until.is_some_and(|until| until.is_done(&c64.registers(), c64.cycles())){
+                run_until = None;
+                debug_mode = true;
+            }
+
             if debug_mode{
                 let state = c64.get_last_state();
                 println!("{:?}", state);
-                loop{
-                    match to64_rx.recv(){
+                let command = loop{
+                    if !running.load(Ordering::SeqCst){
+                        break DebugCommand::Continue;
+                    }
+                    match to64_rx.try_recv(){
                         Ok(c) => {
-                            let mut need_break = false;
-                            for i in c.key_codes{
-                                if i == KeyCode::F5{
-                                    need_break = true;
-                                }
-                                else if i == KeyCode::F7{
-                                    debug_mode = false;
-                                    need_break = true;
-                                }
-                            }
-                            if need_break{
-                                break;
+                            if let Some(command) = debug_key(&c.key_codes, &c64){
+                                break command;
                             }
                         }
+                        Err(TryRecvError::Empty) => {},
                         Err(e) => {
                             eprintln!("Error c64rx {}", e);
                             return;
                         }
                     }
+                    if let Ok(line) = typed_rx.try_recv(){
+                        if line.trim() == "bt"{
+                            show_backtrace(&c64, &symbols);
+                            continue;
+                        }
+                        match parse_debug_command(&line, &c64) {
+                            Some(command) => break command,
+                            None => println!("{}", DEBUG_HELP),
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                };
+                match command {
+                    DebugCommand::Step => {},
+                    DebugCommand::Continue => debug_mode = false,
+                    DebugCommand::Run(command) => {
+                        run_until = Some(RunUntil::new(command, &c64.registers(), c64.cycles()));
+                        debug_mode = false;
+                    }
                 }
                 //let state = c64.get_last_state();
                 //println!("{:?}", state);
@@ -239,7 +358,35 @@ async fn main() {
                 thread::sleep(Duration::from_micros(10));
             }
         }
-        println!("Exiting...");
+        println!("Exiting after {} cycles...", c64.cycles());
+        let ((trace_log, profiler), coverage) = sinks;
+        if let Some(log) = trace_log{
+            let lines = log.lines();
+            match log.finish() {
+                Ok(_) => println!("Trace: {} lines", lines),
+                Err(e) => eprintln!("Trace error: {}", e),
+            }
+        }
+        if let (Some(profiler), Some(path)) = (profiler, profile_path){
+            profiler.write_report(&mut std::io::stdout(), 20).expect("profile report");
+            let written = std::fs::File::create(&path).and_then(|mut file| profiler.write_folded(&mut file));
+            if let Err(e) = written{
+                eprintln!("Profile error: {}", e);
+            }
+        }
+        if let (Some(mut coverage), Some(path)) = (coverage, coverage_path){
+            if let Ok(mut file) = std::fs::File::open(&path){
+                match Coverage::read_bitmap(&mut file) {
+                    Ok(previous) => coverage.merge(&previous),
+                    Err(e) => eprintln!("Coverage error: {}", e),
+                }
+            }
+            coverage.write_report(&mut std::io::stdout()).expect("coverage report");
+            let written = std::fs::File::create(&path).and_then(|mut file| coverage.write_bitmap(&mut file));
+            if let Err(e) = written{
+                eprintln!("Coverage error: {}", e);
+            }
+        }
         c64.show_debug();
         c64.show_screen_ram(true);
     }).expect("thread spawn error");
@@ -0,0 +1,190 @@
+//! Runs Tom Harte's SingleStepTests (ProcessorTests) for the 65x02 from a local checkout.
+//! Every vector sets up the registers and RAM, runs one instruction and compares registers, RAM, each bus cycle
+//! and the cycle count.
+//!
+//! The vectors are not part of the tree, point `HARTE_DIR` at the `65x02` directory of the checkout
+//! (`tests/65x02` by default). `6502/v1` runs against the NMOS core and `wdc65c02/v1` against the 65C02.
+//! `test_harte` is ignored by default, run it with `--ignored`. `HARTE_OPCODES=a9,6c` limits the run to some opcodes.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use rusty6502::cpu6502::disassembler::Instruction;
+use rusty6502::cpu6502::memory::{Memory, Memory6502};
+use rusty6502::cpu6502::{CPU6502, CpuError, CpuRegisters, CpuVariant, StatusRegister};
+
+/// B and bit 5 only exist on the stack.
+const P_MASK: u8 = 0b1100_1111;
+
+struct BusLog{
+    memory: Memory,
+    log: Vec<(u16, u8, bool)>, //address, value, write
+}
+
+impl Memory6502 for BusLog{
+    fn write_memory(&mut self, address: u16, value: u8){
+        self.log.push((address, value, true));
+        self.memory.write_memory(address, value);
+    }
+
+    fn read_memory(&mut self, address: u16) -> u8{
+        let value = self.memory.read_memory(address);
+        self.log.push((address, value, false));
+        value
+    }
+
+    fn read_memory_word(&mut self, address: u16) -> u16{
+        self.memory.read_memory_word(address)
+    }
+
+    fn peek(&self, address: u16) -> u8{
+        self.memory.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8){
+        self.memory.poke(address, value)
+    }
+}
+
+#[derive(Default)]
+struct OpcodeReport{
+    run: usize,
+    skipped: usize,
+    failed: usize,
+    first_failure: Option<String>,
+}
+
+fn number(value: &Value) -> u64{
+    value.as_u64().expect("number in test vector")
+}
+
+fn registers(state: &Value) -> CpuRegisters{
+    CpuRegisters {
+        A: number(&state["a"]) as u8,
+        X: number(&state["x"]) as u8,
+        Y: number(&state["y"]) as u8,
+        SP: number(&state["s"]) as u8,
+        PC: number(&state["pc"]) as u16,
+        P: StatusRegister::new(number(&state["p"]) as u8),
+    }
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)>{
+    state["ram"].as_array().expect("ram").iter().map(|cell| (number(&cell[0]) as u16, number(&cell[1]) as u8)).collect()
+}
+
+/// Runs one vector, `Ok(false)` when it was skipped.
+fn run_vector(vector: &Value, variant: CpuVariant) -> Result<bool, String>{
+    let mut bus = BusLog { memory: Memory::new(64*1024), log: Vec::new() };
+    for (address, value) in ram(&vector["initial"]){
+        bus.memory.write_memory(address, value);
+    }
+
+    let mut cpu = CPU6502::new_variant(variant);
+    cpu.set_registers(registers(&vector["initial"]));
+    match cpu.run_single(&mut bus) {
+        Ok(_) => {}
+        Err(CpuError::Jam { .. }) => return Ok(false), //the vectors keep clocking a jammed CPU
+        Err(error) => return Err(format!("stopped with {}", error)),
+    }
+
+    let expected = registers(&vector["final"]);
+    let got = cpu.registers();
+    if (got.A, got.X, got.Y, got.SP, got.PC) != (expected.A, expected.X, expected.Y, expected.SP, expected.PC)
+        || got.P.value() & P_MASK != expected.P.value() & P_MASK{
+        return Err(format!("registers {:02x?} expected {:02x?}", got, expected));
+    }
+
+    for (address, value) in ram(&vector["final"]){
+        let got = bus.memory.peek(address);
+        if got != value{
+            return Err(format!("ram {:#06x}={:#04x} expected {:#04x}", address, got, value));
+        }
+    }
+
+    let cycles: Vec<(u16, u8, bool)> = vector["cycles"].as_array().expect("cycles").iter()
+        .map(|cycle| (number(&cycle[0]) as u16, number(&cycle[1]) as u8, cycle[2] == "write"))
+        .collect();
+    if bus.log != cycles{
+        return Err(format!("bus {:04x?} expected {:04x?}", bus.log, cycles));
+    }
+    if cpu.last_instruction_cycles() as usize != cycles.len(){
+        return Err(format!("counted {} cycles, expected {}", cpu.last_instruction_cycles(), cycles.len()));
+    }
+    Ok(true)
+}
+
+fn run_directory(dir: &Path, variant: CpuVariant, opcodes: Option<&[u8]>) -> BTreeMap<u8, OpcodeReport>{
+    let mut reports = BTreeMap::new();
+    for opcode in 0..=255u8{
+        if opcodes.is_some_and(|opcodes| !opcodes.contains(&opcode)){
+            continue;
+        }
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let vectors: Value = serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+        let report: &mut OpcodeReport = reports.entry(opcode).or_default();
+        for vector in vectors.as_array().expect("array of vectors"){
+            match run_vector(vector, variant) {
+                Ok(true) => report.run += 1,
+                Ok(false) => report.skipped += 1,
+                Err(reason) => {
+                    report.run += 1;
+                    report.failed += 1;
+                    report.first_failure.get_or_insert_with(|| format!("{}: {}", vector["name"], reason));
+                }
+            }
+        }
+    }
+    reports
+}
+
+/// Prints one line per opcode with failures and returns how many vectors failed.
+fn report(reports: &BTreeMap<u8, OpcodeReport>, variant: CpuVariant) -> usize{
+    let mut failed = 0;
+    for (opcode, report) in reports{
+        if report.failed > 0{
+            let mnemonic = Instruction::decode(0, [*opcode, 0, 0], variant).mnemonic;
+            println!("{:02x} {}: {}/{} failed, first {}", opcode, mnemonic, report.failed, report.run,
+                report.first_failure.as_deref().unwrap_or(""));
+        }
+        failed += report.failed;
+    }
+    let run: usize = reports.values().map(|report| report.run).sum();
+    let skipped: usize = reports.values().map(|report| report.skipped).sum();
+    println!("{:?}: {} opcodes, {} vectors, {} failed, {} skipped", variant, reports.len(), run, failed, skipped);
+    failed
+}
+
+#[test]
+fn test_harte_sample(){
+    let reports = run_directory(Path::new("tests/harte_sample/6502/v1"), CpuVariant::Nmos6502, None);
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports.values().map(|report| report.run).sum::<usize>(), 3);
+    assert_eq!(report(&reports, CpuVariant::Nmos6502), 0);
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests checkout, run with HARTE_DIR set and --ignored"]
+fn test_harte(){
+    let root = std::env::var("HARTE_DIR").unwrap_or_else(|_| "tests/65x02".to_owned());
+    let opcodes: Option<Vec<u8>> = std::env::var("HARTE_OPCODES").ok()
+        .map(|list| list.split(',').map(|op| u8::from_str_radix(op.trim(), 16).expect("hex opcode")).collect());
+
+    let mut failed = 0;
+    let mut found = false;
+    for (dir, variant) in [("6502/v1", CpuVariant::Nmos6502), ("wdc65c02/v1", CpuVariant::Cmos65C02)]{
+        let dir = Path::new(&root).join(dir);
+        if dir.is_dir(){
+            found = true;
+            failed += report(&run_directory(&dir, variant, opcodes.as_deref()), variant);
+        }
+    }
+    assert!(found, "No SingleStepTests in {}, set HARTE_DIR to the 65x02 directory of a checkout", root);
+    assert_eq!(failed, 0);
+}
@@ -0,0 +1,137 @@
+use rusty6502::c64::C64;
+use rusty6502::c64::c64memory::C64Memory;
+use rusty6502::cpu6502::CpuVariant;
+use rusty6502::cpu6502::assembler::assemble;
+use rusty6502::cpu6502::coverage::{Coverage, OPCODE, READ, WRITE};
+use rusty6502::cpu6502::memory::{Bank, Memory6502, View};
+use rusty6502::cpu6502::stepping::RunCommand;
+
+/// A kernal that prints to the screen and stops, enough to boot the machine without the real ROMs.
+fn test_kernal() -> Vec<u8>{
+    let code = assemble("
+                ldx #0
+        loop:   lda message,x
+                beq done
+                sta $0400,x
+                inx
+                bne loop
+        done:   jmp done
+        message: .byte 8, 9, 0      ; HI in screen codes
+    ", 0xe000, CpuVariant::Nmos6502).unwrap();
+
+    let mut kernal = vec![0xea; 8*1024];
+    kernal[..code.len()].copy_from_slice(&code);
+    kernal[0x1ffc] = 0x00; //reset vector $e000
+    kernal[0x1ffd] = 0xe0;
+    kernal
+}
+
+#[test]
+fn test_headless_c64(){
+    let memory = C64Memory::with_roms(test_kernal(), vec![0; 8*1024], vec![0; 4*1024]);
+    let mut c64 = C64::with_memory(memory);
+    c64.reset();
+
+    for _i in 0..100{
+        c64.run_single().unwrap();
+    }
+
+    let screen = c64.get_character_ram();
+    assert_eq!(&screen.ram[..3], &[0x08, 0x09, 0x00]);
+    assert!(c64.cycles() > 0);
+
+    c64.reset();
+    assert_eq!(c64.get_character_ram().ram[0], 0x00);
+}
+
+#[test]
+fn test_c64_coverage(){
+    let memory = C64Memory::with_roms(test_kernal(), vec![0; 8*1024], vec![0; 4*1024]);
+    let mut c64 = C64::with_memory(memory);
+    c64.reset();
+
+    let mut coverage = Coverage::new();
+    for _i in 0..100{
+        c64.run_single_traced(&mut coverage).unwrap();
+    }
+
+    assert_eq!(coverage.get(Bank::Rom, 0xe000) & OPCODE, OPCODE);
+    assert_eq!(coverage.get(Bank::Ram, 0xe000), 0);
+    assert_eq!(coverage.get(Bank::Ram, 0x0400), WRITE);
+    let message = coverage.ranges(Bank::Rom, READ);
+    assert_eq!(message.len(), 1);
+    assert_eq!(message[0].clone().count(), 3);
+}
+
+#[test]
+fn test_c64_peek_poke(){
+    let memory = C64Memory::with_roms(test_kernal(), vec![0; 8*1024], vec![0; 4*1024]);
+    let mut c64 = C64::with_memory(memory);
+    c64.reset();
+
+    // ROM over RAM, a poke lands in the RAM below like a CPU write
+    assert_eq!(c64.peek(0xe000), 0xa2);
+    c64.poke(0xe000, 0x55);
+    assert_eq!(c64.peek(0xe000), 0xa2);
+    assert_eq!(c64.peek_view(View::Ram, 0xe000), 0x55);
+    assert_eq!(c64.peek_view(View::Rom, 0xe000), 0xa2);
+    c64.poke_view(View::Rom, 0xd000, 0x3c);
+    assert_eq!(c64.peek_view(View::Rom, 0xd000), 0x3c);
+    assert_eq!(c64.peek(0xd000), 0x00); //IO is banked in
+
+    // The VIC sees the character ROM in bank 0 and plain RAM in bank 1
+    assert_eq!(c64.peek_view(View::Vic, 0x1000), 0x3c);
+    c64.poke_view(View::Ram, 0x4400, 0x07);
+    assert_eq!(c64.peek_view(View::Vic, 0x0400), 0x00);
+    c64.poke(0xdd00, 0x02);
+    assert_eq!(c64.peek_view(View::Vic, 0x0400), 0x07);
+
+    // Peeking the interrupt flags leaves them set, timer A counts CPU cycles
+    c64.poke(0xdc04, 0x10);
+    c64.poke(0xdc05, 0x00);
+    c64.poke(0xdc0e, 0x01);
+    let mut flagged = false;
+    for _i in 0..10{
+        c64.run_single().unwrap();
+        if c64.peek(0xdc0d) != 0{
+            flagged = true;
+            break;
+        }
+    }
+    assert!(flagged);
+    assert_eq!(c64.peek(0xdc0d), 0x81);
+    assert_eq!(c64.peek(0xdc0d), 0x81);
+
+    // The time of day runs on the cycle counter too and peeking it does not latch it
+    assert_eq!(c64.peek(0xdc08), 0);
+    c64.run_command(RunCommand::Cycles(98_525), &mut ()).unwrap();
+    assert_eq!(c64.peek(0xdc0b), 0);
+    assert_eq!(c64.peek(0xdc08), 1);
+    c64.run_command(RunCommand::Cycles(98_525), &mut ()).unwrap();
+    assert_eq!(c64.peek(0xdc08), 2);
+}
+
+#[test]
+fn test_c64_run_to_raster_line(){
+    let memory = C64Memory::with_roms(test_kernal(), vec![0; 8*1024], vec![0; 4*1024]);
+    let mut c64 = C64::with_memory(memory);
+    c64.reset();
+
+    c64.run_command(RunCommand::Cycles(c64.cycles_to_raster_line(100)), &mut ()).unwrap();
+    assert_eq!(c64.raster_line(), 100);
+    assert_eq!(c64.peek(0xd012), 100);
+    assert_eq!(c64.peek(0xd011), 0x1b);
+    let cycles = c64.cycles();
+    c64.run_command(RunCommand::Cycles(c64.cycles_to_raster_line(0)), &mut ()).unwrap();
+    assert_eq!(c64.raster_line(), 0);
+    assert_eq!((c64.cycles() - cycles) / 63, 312 - 100);
+    c64.run_command(RunCommand::Cycles(c64.cycles_to_raster_line(c64.raster_line() + 1)), &mut ()).unwrap();
+    assert_eq!(c64.raster_line(), 1);
+
+    // The CPU reads the line of the bus cycle, bit 8 is in $D011
+    let mut memory = C64Memory::with_roms(test_kernal(), vec![0; 8*1024], vec![0; 4*1024]);
+    memory.set_cycles(300 * 63 + 61);
+    assert_eq!(memory.read_memory(0xd012), 0x2c); //line 300
+    assert_eq!(memory.read_memory(0xd011), 0x9b);
+    assert_eq!(memory.read_memory(0xd012), 0x2d);
+}
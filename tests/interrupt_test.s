@@ -0,0 +1,256 @@
+; Interrupt test in the syntax of the built in assembler, assemble at $0400.
+; Covers the cases of Klaus Dormann's 6502_interrupt_test, which test_klaus_interrupts
+; runs from tests/6502_interrupt_test.bin, and the NMI taking over BRK its build leaves out.
+;
+; Devices are replaced by a feedback port: bit 0 holds IRQ low, bit 1 holds NMI low.
+; The IRQ/BRK vector must point to $0403 and the NMI vector to $0406.
+;
+; Returns:
+;   result = 0 if the test passed
+;   result = number of the failing test otherwise
+; in both cases the program ends trapped in a jmp *
+
+port      = $bffc
+result    = $02
+irq_count = $03
+irq_p     = $04
+irq_ret   = $05
+nmi_count = $07
+nmi_p     = $08
+nmi_ret   = $09
+nmi_hold  = $0b         ; the NMI handler leaves the line low when set
+
+start:  jmp main
+        jmp irq
+        jmp nmi
+
+; Handlers record the pushed status and return address, then release their line
+irq:    pha
+        txa
+        pha
+        tsx
+        lda $0103,x
+        sta irq_p
+        lda $0104,x
+        sta irq_ret
+        lda $0105,x
+        sta irq_ret+1
+        inc irq_count
+        lda port
+        and #$fe
+        sta port
+        pla
+        tax
+        pla
+        rti
+
+nmi:    pha
+        txa
+        pha
+        tsx
+        lda $0103,x
+        sta nmi_p
+        lda $0104,x
+        sta nmi_ret
+        lda $0105,x
+        sta nmi_ret+1
+        inc nmi_count
+        lda nmi_hold
+        bne nmi_exit
+        lda port
+        and #$fd
+        sta port
+nmi_exit: pla
+        tax
+        pla
+        rti
+
+; A = expected count, X/Y = expected return address
+check_irq: cmp irq_count
+        bne fail
+        cpx irq_ret
+        bne fail
+        cpy irq_ret+1
+        bne fail
+        rts
+
+check_nmi: cmp nmi_count
+        bne fail
+        cpx nmi_ret
+        bne fail
+        cpy nmi_ret+1
+        bne fail
+        rts
+
+; A = expected B and I flags of the pushed status
+check_irq_p: eor irq_p
+        and #$14
+        bne fail
+        rts
+
+check_nmi_p: eor nmi_p
+        and #$14
+        bne fail
+        rts
+
+fail:   jmp fail
+
+main:   cld
+        ldx #$ff
+        txs
+        lda #0
+        sta port
+        sta irq_count
+        sta irq_ret
+        sta irq_ret+1
+        sta nmi_count
+        sta nmi_ret
+        sta nmi_ret+1
+        sta nmi_hold
+
+; 1: IRQ waits while I is set, CLI lets it in after the next instruction
+        lda #1
+        sta result
+        sei
+        sta port
+        nop
+        nop
+        lda #0
+        ldx #0
+        ldy #0
+        jsr check_irq
+        cli
+        nop
+t1:     sei
+        lda #1
+        ldx #<t1
+        ldy #>t1
+        jsr check_irq
+        lda #$00
+        jsr check_irq_p
+
+; 2: SEI right after CLI is polled with I still clear, the pushed status has I set
+        lda #2
+        sta result
+        lda #1
+        sta port
+        nop
+        cli
+        sei
+t2:     lda #2
+        ldx #<t2
+        ldy #>t2
+        jsr check_irq
+        lda #$04
+        jsr check_irq_p
+
+; 3: PLP clearing I takes effect after the next instruction
+        lda #3
+        sta result
+        lda #1
+        sta port
+        lda #0
+        pha
+        plp
+        nop
+t3:     sei
+        lda #3
+        ldx #<t3
+        ldy #>t3
+        jsr check_irq
+        lda #$00
+        jsr check_irq_p
+
+; 4: a store raising IRQ in its last cycle is too late for its own poll
+        lda #4
+        sta result
+        cli
+        lda #1
+        sta port
+        nop
+t4:     sei
+        lda #4
+        ldx #<t4
+        ldy #>t4
+        jsr check_irq
+
+; 5: NMI ignores I and fires once per falling edge
+        lda #5
+        sta result
+        lda #1
+        sta nmi_hold
+        lda #2
+        sta port
+        nop
+t5:     nop
+        nop
+        nop
+        lda #1
+        ldx #<t5
+        ldy #>t5
+        jsr check_nmi
+        lda #$04
+        jsr check_nmi_p
+        lda #0
+        sta nmi_hold
+        sta port
+        lda #2
+        sta port
+        nop
+t5b:    lda #2
+        ldx #<t5b
+        ldy #>t5b
+        jsr check_nmi
+
+; 6: BRK skips its signature byte and pushes B
+        lda #6
+        sta result
+        brk
+        .byte $ff
+t6:     lda #5
+        ldx #<t6
+        ldy #>t6
+        jsr check_irq
+        lda #$14
+        jsr check_irq_p
+
+; 7: NMI arriving while BRK starts takes its vector, B is still pushed
+        lda #7
+        sta result
+        lda #2
+        sta port
+        brk
+        .byte $ff
+t7:     lda #3
+        ldx #<t7
+        ldy #>t7
+        jsr check_nmi
+        lda #$14
+        jsr check_nmi_p
+        lda #5
+        ldx #<t6
+        ldy #>t6
+        jsr check_irq
+
+; 8: NMI wins over IRQ, RTI restores I right away and the IRQ follows
+        lda #8
+        sta result
+        cli
+        lda #3
+        sta port
+        nop
+t8:     sei
+        lda #4
+        ldx #<t8
+        ldy #>t8
+        jsr check_nmi
+        lda #6
+        ldx #<t8
+        ldy #>t8
+        jsr check_irq
+        lda #$00
+        jsr check_irq_p
+
+        lda #0
+        sta result
+done:   jmp done